@@ -5,11 +5,22 @@ use crate::{ProgramState, UIState};
 use std::f32::consts::{PI, TAU};
 use bevy_egui::{egui, EguiContextPass, EguiContexts};
 use rand::prelude::*;
-use crate::common::Modifier;
+use crate::canvas::{Canvas, CanvasCamera};
+use crate::common::{color_edit, cursor_to_plane, gamut_map, Modifier, Random, SpatialGrid};
+use crate::common::noise::Noise;
+use crate::mask::MaskSource;
+use crate::palette::{Palettes, Role};
+use crate::recording::Painter;
 
 #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
 struct BubbleSet;
 
+const WATER_Z: f32 = 0.0;
+const BUBBLE_Z: f32 = 1.0;
+const RAY_Z: f32 = 2.0;
+/// Distance in z between bubble depth layers.
+const LAYER_SPACING: f32 = 0.05;
+
 pub struct BubbleArt;
 
 impl Plugin for BubbleArt {
@@ -20,13 +31,22 @@ impl Plugin for BubbleArt {
                                     BubbleSet.run_if(in_state(ProgramState::Bubbles)),
                                 ))
             .init_resource::<Rays>()
+            .init_resource::<GeneratedRays>()
             .init_resource::<Bubbles>()
+            .init_resource::<RisingBubbles>()
+            .init_resource::<Water>()
+            .init_resource::<Pointer>()
             .add_systems(EguiContextPass, params_ui)
             .add_systems(
                 Update,
                     (
+                        apply_palette.run_if(resource_changed::<Palettes>),
+                        reseed.run_if(resource_changed::<Random>),
+                        interact,
+                        simulate,
+                        generate_rays,
                         draw,
-                    ).in_set(BubbleSet)
+                    ).chain().in_set(BubbleSet)
             )
         ;
     }
@@ -38,13 +58,28 @@ fn params_ui(
     program_state: Res<State<ProgramState>>,
     mut bubbles: ResMut<Bubbles>,
     mut rays: ResMut<Rays>,
+    mut water: ResMut<Water>,
+    mut palettes: ResMut<Palettes>,
+    mut rand: ResMut<Random>,
 ) {
     if !ui_state.params_panel || !program_state.eq(&ProgramState::Bubbles) {
         return;
     }
 
     egui::Window::new("Params").show(contexts.ctx_mut(), |ui| {
+        ui.heading("Palette");
+        if palettes.bypass_change_detection().ui(ui) {
+            palettes.set_changed();
+        }
+        color_edit(ui, "Bubbles", &mut bubbles.color);
+
+        ui.heading("Random");
+        if rand.bypass_change_detection().ui(ui) {
+            rand.set_changed();
+        }
+
         ui.heading("Steps");
+        ui.checkbox(&mut water.render, "Water");
         ui.checkbox(&mut bubbles.render, "Bubbles");
         ui.checkbox(&mut rays.render, "Rays");
 
@@ -62,16 +97,73 @@ fn params_ui(
         ui.add(egui::Slider::new(&mut bubbles.shine_end, 0.0..=TAU).text("End"));
         ui.add(egui::Slider::new(&mut bubbles.shine_thickness, 0.01..=0.1).text("Thickness"));
 
+        ui.add(egui::Slider::new(&mut bubbles.highlights, 1..=4).text("Highlights"));
+
+        ui.heading("Film");
+        ui.checkbox(&mut bubbles.iridescent, "Iridescent");
+        ui.add(egui::Slider::new(&mut bubbles.iridescence, 0.0..=1.0).text("Intensity"));
+        ui.add(egui::Slider::new(&mut bubbles.hue_shift, 0.0..=360.0).text("Hue Shift"));
+        ui.add(egui::Slider::new(&mut bubbles.hue_speed, -1.0..=1.0).text("Hue Speed"));
+        ui.add(egui::Slider::new(&mut bubbles.fill_alpha, 0.0..=0.5).text("Fill"));
+
         ui.heading("Wobble");
         ui.add(egui::Slider::new(&mut bubbles.wobble_size, 0.0..=4.0).text("Size"));
-        ui.add(egui::Slider::new(&mut bubbles.wobble_frequency, 0.0..=20.0).text("Frequency"));
+        ui.add(egui::Slider::new(&mut bubbles.wobble_frequency, 0.0..=5.0).text("Frequency"));
 
         ui.heading("Positioning");
-        ui.add(egui::Slider::new(&mut bubbles.starting, 0.0..=10.0).text("Starting"));
+        ui.add(egui::Slider::new(&mut bubbles.starting, 0.0..=5.0).text("Starting"));
         ui.add(egui::Slider::new(&mut bubbles.starting_range, 0.0..=5.0).text("Range"));
+        ui.add(egui::Slider::new(&mut bubbles.surface, 0.0..=5.0).text("Surface"));
+        bubbles.mask.ui(ui);
+
+        ui.heading("Lifecycle");
+        ui.add(egui::Slider::new(&mut bubbles.spawn_rate, 0.0..=60.0).text("Spawn Rate"));
+        ui.add(egui::Slider::new(&mut bubbles.lifetime, 0.5..=30.0).text("Lifetime"));
+        ui.add(egui::Slider::new(&mut bubbles.buoyancy, 0.0..=20.0).text("Buoyancy"));
+        ui.add(egui::Slider::new(&mut bubbles.drag, 0.0..=5.0).text("Drag"));
+        ui.add(egui::Slider::new(&mut bubbles.pop_chance, 0.0..=1.0).text("Pop Chance"));
+        ui.add(egui::Slider::new(&mut bubbles.pop_duration, 0.05..=2.0).text("Pop Duration"));
+
+        ui.heading("Depth");
+        ui.add(egui::Slider::new(&mut bubbles.layers, 1..=6).text("Layers"));
+        ui.add(egui::Slider::new(&mut bubbles.layer_falloff, 0.2..=1.0).text("Size Falloff"));
+        ui.add(egui::Slider::new(&mut bubbles.layer_alpha, 0.0..=1.0).text("Alpha Falloff"));
+        ui.add(egui::Slider::new(&mut bubbles.layer_desaturation, 0.0..=1.0).text("Desaturation"));
+        ui.add(egui::Slider::new(&mut bubbles.drift_speed, 0.0..=1.0).text("Drift Speed"));
+        ui.add(egui::Slider::new(&mut bubbles.drift_size, 0.0..=5.0).text("Drift Size"));
+
+        ui.heading("Cursor");
+        ui.add(egui::Slider::new(&mut bubbles.push_radius, 0.0..=10.0).text("Push Radius"));
+        ui.add(egui::Slider::new(&mut bubbles.push_strength, 0.0..=50.0).text("Push Strength"));
+
+        ui.heading("Collisions");
+        ui.checkbox(&mut bubbles.collide, "Collide");
+        ui.add(egui::Slider::new(&mut bubbles.repulsion, 0.0..=30.0).text("Repulsion"));
+        ui.add(egui::Slider::new(&mut bubbles.merge_chance, 0.0..=5.0).text("Merge Chance"));
+
+        ui.heading("Water");
+        color_edit(ui, "Surface", &mut water.top_color);
+        color_edit(ui, "Depths", &mut water.bottom_color);
+        ui.add(egui::Slider::new(&mut water.bands, 1..=128).text("Gradient Bands"));
+        ui.checkbox(&mut water.caustics, "Caustics");
+        ui.add(egui::Slider::new(&mut water.caustic_count, 0..=200).text("Caustic Count"));
+        ui.add(egui::Slider::new(&mut water.caustic_depth, 0.0..=1.0).text("Caustic Depth"));
+        ui.add(egui::Slider::new(&mut water.caustic_size, 0.1..=5.0).text("Caustic Size"));
+        ui.add(egui::Slider::new(&mut water.caustic_alpha, 0.0..=1.0).text("Caustic Alpha"));
+        ui.add(egui::Slider::new(&mut water.caustic_speed, 0.0..=5.0).text("Caustic Speed"));
+        ui.checkbox(&mut water.particulate, "Particulate");
+        ui.add(egui::Slider::new(&mut water.particle_count, 0..=500).text("Particle Count"));
+        ui.add(egui::Slider::new(&mut water.particle_size, 0.005..=0.2).text("Particle Size"));
+        ui.add(egui::Slider::new(&mut water.particle_alpha, 0.0..=1.0).text("Particle Alpha"));
+        ui.add(egui::Slider::new(&mut water.particle_drift, 0.0..=2.0).text("Particle Drift"));
 
         ui.heading("Rays");
+        ui.add(egui::Slider::new(&mut rays.origin.x, -30.0..=30.0).text("Origin X"));
+        ui.add(egui::Slider::new(&mut rays.origin.y, -30.0..=30.0).text("Origin Y"));
+        ui.add(egui::Slider::new(&mut rays.count, 1..=300).text("Count"));
         ui.add(egui::Slider::new(&mut rays.angle, 0.0..=TAU).text("Angle"));
+        ui.add(egui::Slider::new(&mut rays.spread, 0.0..=PI).text("Spread"));
+        color_edit(ui, "Tint", &mut rays.tint);
         ui.add(egui::Slider::new(&mut rays.min_length, 0.0..=10.0).text("Min Length").step_by(0.1));
         ui.add(egui::Slider::new(&mut rays.max_length, 0.1..=10.0).text("Max Length").step_by(0.1));
         ui.add(egui::Slider::new(&mut rays.thickness, 0.0..=1.0).text("Thickness"));
@@ -80,33 +172,127 @@ fn params_ui(
     });
 }
 
-static CYCLE: f32 = 4.0;
+/// Takes the water, bubble and ray colours from the active palette, or puts back the piece's own without one.
+fn apply_palette(palettes: Res<Palettes>, mut bubbles: ResMut<Bubbles>, mut rays: ResMut<Rays>, mut water: ResMut<Water>) {
+    bubbles.color = palettes.pick(Role::Primary, BLUE.pastel_very());
+    water.top_color = palettes.pick(Role::Secondary, BLUE.pastel());
+    water.bottom_color = palettes.pick(Role::Background, NAVY.pastel());
+    rays.tint = palettes.pick(Role::Accent, YELLOW.pastel_very());
+}
+
+/// Rebuilds the wobble from the shared seed, so the same seed wobbles the same way.
+fn reseed(rand: Res<Random>, mut bubbles: ResMut<Bubbles>) {
+    bubbles.noise = Noise::new(rand.stream("bubbles").stream("wobble").u64(0));
+}
 
-fn draw(mut painter: ShapePainter, time: Res<Time>, windows: Query<&Window>, bubbles: Res<Bubbles>, rays: Res<Rays>) {
+fn draw(
+    mut painter: Painter,
+    time: Res<Time>,
+    canvas: Res<Canvas>,
+    bubbles: Res<Bubbles>,
+    rising: Res<RisingBubbles>,
+    rays: Res<Rays>,
+    generated: Res<GeneratedRays>,
+    water: Res<Water>,
+    pointer: Res<Pointer>,
+    rand: Res<Random>,
+) {
+    painter.describe(&*bubbles);
+    painter.describe(&*rays);
+    painter.describe(&*water);
     let seconds = time.elapsed_secs();
+    let rand = rand.stream("bubbles");
     // let start_pos = painter.transform;
 
-    let window: &Window = windows.single().unwrap();
-    let width = window.resolution.width();
-    let height = window.resolution.height();
-
-    painter.set_color(BLUE.pastel());
-    painter.rect(Vec2::new(width, height));
+    if water.render {
+        water.draw(&mut painter, seconds, canvas.half_size(), &rand);
+    }
 
     // Draw bubbles
     if bubbles.render {
-        bubbles.draw(&mut painter, seconds);
+        rising.draw(&mut painter, &bubbles, seconds);
     }
 
     if rays.render {
-        rays.draw(&mut painter, seconds);
+        generated.draw(&mut painter, &rays, seconds);
+        if pointer.dragging_origin {
+            rays.draw_handle(&mut painter);
+        }
+    }
+}
+
+/// Radius around `Rays::origin` that can be grabbed with the cursor.
+const ORIGIN_HANDLE: f32 = 0.6;
+
+#[derive(Resource, Default)]
+struct Pointer {
+    dragging_origin: bool,
+}
+
+/// Clicking pops the bubble under the cursor, holding pushes bubbles away, and the ray origin can be dragged.
+fn interact(
+    mut contexts: EguiContexts,
+    windows: Query<&Window>,
+    cameras: Query<(&Camera, &GlobalTransform), With<CanvasCamera>>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    time: Res<Time>,
+    mut pointer: ResMut<Pointer>,
+    bubbles: Res<Bubbles>,
+    mut rising: ResMut<RisingBubbles>,
+    mut rays: ResMut<Rays>,
+) {
+    if buttons.just_released(MouseButton::Left) {
+        pointer.dragging_origin = false;
+    }
+
+    let ctx = contexts.ctx_mut();
+    if ctx.wants_pointer_input() || ctx.is_pointer_over_area() {
+        return;
+    }
+    let (Ok(window), Ok((camera, camera_transform))) = (windows.single(), cameras.single()) else {
+        return;
+    };
+    let (Some(bubble_cursor), Some(ray_cursor)) = (
+        cursor_to_plane(window, camera, camera_transform, BUBBLE_Z),
+        cursor_to_plane(window, camera, camera_transform, RAY_Z),
+    ) else {
+        return;
+    };
+
+    let seconds = time.elapsed_secs();
+    if buttons.just_pressed(MouseButton::Left) {
+        if rays.render && ray_cursor.distance(rays.origin) <= ORIGIN_HANDLE {
+            pointer.dragging_origin = true;
+        } else {
+            rising.pop_at(&bubbles, bubble_cursor, seconds);
+        }
+    }
+
+    if buttons.pressed(MouseButton::Left) {
+        if pointer.dragging_origin {
+            rays.origin = ray_cursor;
+        } else {
+            rising.push_from(&bubbles, bubble_cursor, seconds, time.delta_secs());
+        }
+    }
+}
+
+fn simulate(time: Res<Time>, canvas: Res<Canvas>, bubbles: Res<Bubbles>, mut rising: ResMut<RisingBubbles>) {
+    rising.simulate(&bubbles, time.elapsed_secs(), time.delta_secs(), canvas.half_size());
+}
+
+/// Rebuilds the rays when the count changes.
+fn generate_rays(rays: Res<Rays>, mut generated: ResMut<GeneratedRays>) {
+    if generated.rays.len() != rays.count {
+        *generated = GeneratedRays::generate(rays.count);
     }
 }
 
-#[derive(Resource)]
+#[derive(Resource, Debug)]
 struct Bubbles {
-    bubbles: Vec<Bubble>,
     render: bool,
+    /// Further layers fade and grey this.
+    color: Srgba,
     thickness: f32,
     shine_start: f32,
     shine_end: f32,
@@ -115,41 +301,117 @@ struct Bubbles {
     outer_radius_max: f32,
     inner_radius_min: f32,
     inner_radius_max: f32,
+    /// Roughly how many times a second the wobble changes direction.
     wobble_frequency: f32,
     wobble_size: f32,
+    /// Drives the wobble, so each bubble sways smoothly rather than back and forth on a sine.
+    /// Follows the seed in `Random`.
+    noise: Noise,
     starting: f32,
     starting_range: f32,
+    surface: f32,
+    spawn_rate: f32,
+    lifetime: f32,
+    buoyancy: f32,
+    drag: f32,
+    pop_chance: f32,
+    pop_duration: f32,
+    collide: bool,
+    repulsion: f32,
+    merge_chance: f32,
+    push_radius: f32,
+    push_strength: f32,
+    iridescent: bool,
+    iridescence: f32,
+    /// Rotates the rainbow around the rim, in degrees.
+    hue_shift: f32,
+    /// Turns of the rainbow around the rim per second.
+    hue_speed: f32,
+    highlights: usize,
+    fill_alpha: f32,
+    layers: usize,
+    layer_falloff: f32,
+    layer_alpha: f32,
+    layer_desaturation: f32,
+    drift_speed: f32,
+    drift_size: f32,
+    /// Bubbles spawn along the dark parts of the mask, stretched over the spawn area.
+    mask: MaskSource,
 }
 
 impl Bubbles {
-    fn draw(&self, painter: &mut ShapePainter, seconds: f32) {
-        painter.set_color(BLUE.pastel_very());
-        for bubble in &self.bubbles {
-            bubble.draw(painter, seconds, self);
-        }
+    /// Size and speed multiplier for a layer, with layer 0 at the front.
+    fn layer_scale(&self, layer: usize) -> f32 {
+        self.layer_falloff.powi(layer as i32)
     }
-}
 
-impl Default for Bubbles {
-    fn default() -> Self {
-        let mut rng = rand::rng();
-        let mut bubbles = Vec::new();
+    /// Further layers are fainter and greyer, as if seen through more water.
+    fn layer_color(&self, layer: usize) -> Srgba {
+        let depth = layer as f32 / self.layers.max(1) as f32;
+        self.color
+            .desaturate(self.layer_desaturation * depth)
+            .with_alpha(self.layer_alpha.powi(layer as i32))
+    }
 
-        let width = 20.0;
-        let scale_range = 0.4;
+    /// Offset of the slow camera drift for a layer. Nearer layers move further, giving parallax.
+    fn drift(&self, layer: usize, seconds: f32) -> Vec2 {
+        let t = seconds * self.drift_speed;
+        Vec2::new(t.sin(), (t * 0.7).cos() * 0.5) * self.drift_size * self.layer_scale(layer)
+    }
+
+    /// Pushes overlapping bubbles apart, and sometimes merges them into one with the same total area.
+    fn resolve_collisions(&self, bubbles: &mut Vec<Bubble>, seconds: f32, dt: f32, rng: &mut impl Rng) {
+        let positions: Vec<Vec2> = bubbles.iter().map(|bubble| bubble.pos(seconds, self)).collect();
+        let radii: Vec<f32> = bubbles.iter().map(|bubble| bubble.outer_radius(self)).collect();
 
-        for _ in 0..50 {
-            let x = rng.random::<f32>() * width - (width / 2.0);
-            let spawn_offset = rng.random::<f32>();
-            let wobble_offset = rng.random::<f32>();
-            let scale = rng.random::<f32>() * scale_range + (1.0 - (scale_range / 2.0));
-            bubbles.push(Bubble { x, spawn_offset, wobble_offset, scale });
+        let max_radius = radii.iter().copied().fold(0.0, f32::max);
+        let mut grid = SpatialGrid::new(max_radius * 2.0);
+        for (idx, pos) in positions.iter().enumerate() {
+            grid.insert(idx, *pos);
         }
 
-        Self {
-            bubbles,
+        let mut merged = vec![false; bubbles.len()];
+        for i in 0..bubbles.len() {
+            if merged[i] || bubbles[i].popped.is_some() {
+                continue;
+            }
+            for j in grid.nearby(positions[i]) {
+                if j <= i || merged[j] || bubbles[j].popped.is_some() || bubbles[i].layer != bubbles[j].layer {
+                    continue;
+                }
+                let offset = positions[j] - positions[i];
+                let overlap = radii[i] + radii[j] - offset.length();
+                if overlap <= 0.0 {
+                    continue;
+                }
+
+                if rng.random::<f32>() < self.merge_chance * dt {
+                    let (area_i, area_j) = (radii[i].powi(2), radii[j].powi(2));
+                    let weight = area_j / (area_i + area_j);
+                    bubbles[i].pos += offset * weight;
+                    bubbles[i].rise = bubbles[i].rise.lerp(bubbles[j].rise, weight);
+                    bubbles[i].scale *= (area_i + area_j).sqrt() / radii[i];
+                    merged[j] = true;
+                    // The radius of i has changed, so leave its other contacts for the next frame.
+                    break;
+                }
+
+                let push = offset.normalize_or_zero() * overlap * self.repulsion * dt * 0.5;
+                bubbles[i].pos -= push;
+                bubbles[j].pos += push;
+            }
+        }
 
+        let mut merged = merged.into_iter();
+        bubbles.retain(|_| !merged.next().unwrap_or(false));
+    }
+}
+
+impl Default for Bubbles {
+    fn default() -> Self {
+        Self {
             render: true,
+            color: BLUE.pastel_very(),
             thickness: 0.01,
             shine_start: PI / 6.0,
             shine_end: PI / 3.0,
@@ -158,60 +420,412 @@ impl Default for Bubbles {
             outer_radius_max: 0.5,
             inner_radius_min: 0.3,
             inner_radius_max: 0.4,
-            wobble_frequency: 10.0,
+            wobble_frequency: 1.0,
             wobble_size: 1.5,
-            starting: 7.0,
-            starting_range: 5.0,
+            noise: Noise::default(),
+            // How far below the bottom of the canvas bubbles start, and below its top they pop.
+            starting: 1.0,
+            starting_range: 1.0,
+            surface: 0.5,
+            spawn_rate: 12.0,
+            lifetime: 8.0,
+            buoyancy: 4.0,
+            drag: 0.8,
+            pop_chance: 0.02,
+            pop_duration: 0.3,
+            collide: false,
+            repulsion: 8.0,
+            merge_chance: 0.5,
+            push_radius: 3.0,
+            push_strength: 15.0,
+            iridescent: false,
+            iridescence: 0.7,
+            hue_shift: 0.0,
+            hue_speed: 0.1,
+            highlights: 1,
+            fill_alpha: 0.0,
+            layers: 3,
+            layer_falloff: 0.7,
+            layer_alpha: 0.6,
+            layer_desaturation: 0.6,
+            drift_speed: 0.1,
+            drift_size: 1.5,
+            mask: MaskSource::default(),
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+struct RisingBubbles {
+    bubbles: Vec<Bubble>,
+    /// Fractional bubbles owed by the spawn rate, carried between frames.
+    spawn_debt: f32,
+}
+
+impl RisingBubbles {
+    fn draw(&self, painter: &mut Painter, params: &Bubbles, seconds: f32) {
+        for bubble in &self.bubbles {
+            painter.set_color(params.layer_color(bubble.layer(params)));
+            bubble.draw(painter, seconds, params);
+        }
+    }
+
+    fn simulate(&mut self, params: &Bubbles, seconds: f32, dt: f32, extent: Vec2) {
+        let mut rng = rand::rng();
+
+        self.spawn_debt += params.spawn_rate * dt;
+        while self.spawn_debt >= 1.0 {
+            self.spawn_debt -= 1.0;
+            if let Some(bubble) = Bubble::spawn(params, extent, &mut rng) {
+                self.bubbles.push(bubble);
+            }
+        }
+
+        for bubble in &mut self.bubbles {
+            match bubble.popped.as_mut() {
+                Some(popped) => *popped += dt,
+                None => {
+                    bubble.age += dt;
+                    let buoyancy = params.buoyancy * bubble.scale * params.layer_scale(bubble.layer(params));
+                    bubble.rise += (buoyancy - params.drag * bubble.rise) * dt;
+                    bubble.pos.y += bubble.rise * dt;
+
+                    let at_surface = bubble.pos.y + bubble.outer_radius(params) >= extent.y - params.surface;
+                    let unlucky = rng.random::<f32>() < params.pop_chance * dt;
+                    if at_surface || unlucky || bubble.age >= params.lifetime {
+                        bubble.popped = Some(0.0);
+                    }
+                }
+            }
+        }
+
+        if params.collide {
+            params.resolve_collisions(&mut self.bubbles, seconds, dt, &mut rng);
+        }
+
+        self.bubbles.retain(|bubble| bubble.popped.is_none_or(|popped| popped < params.pop_duration));
+    }
+
+    /// Pops the closest bubble containing `cursor`, if there is one.
+    fn pop_at(&mut self, params: &Bubbles, cursor: Vec2, seconds: f32) {
+        let hit = self.bubbles.iter()
+            .enumerate()
+            .filter(|(_, bubble)| bubble.popped.is_none())
+            .map(|(idx, bubble)| (idx, bubble.pos(seconds, params).distance(cursor), bubble.outer_radius(params)))
+            .filter(|(_, distance, radius)| distance <= radius)
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+
+        if let Some((idx, _, _)) = hit {
+            self.bubbles[idx].popped = Some(0.0);
+        }
+    }
+
+    /// Shoves bubbles near `cursor` away from it, harder the closer they are.
+    fn push_from(&mut self, params: &Bubbles, cursor: Vec2, seconds: f32, dt: f32) {
+        for bubble in &mut self.bubbles {
+            let offset = bubble.pos(seconds, params) - cursor;
+            let distance = offset.length();
+            if distance < params.push_radius {
+                let falloff = 1.0 - distance / params.push_radius;
+                bubble.pos += offset.normalize_or_zero() * falloff * params.push_strength * dt;
+            }
         }
     }
 }
 
 struct Bubble {
-    x: f32,
-    spawn_offset: f32,
+    pos: Vec2,
+    /// Upward speed.
+    rise: f32,
+    age: f32,
     wobble_offset: f32,
     scale: f32,
+    layer: usize,
+    /// Seconds since the bubble popped, if it has.
+    popped: Option<f32>,
 }
 
 impl Bubble {
-    fn draw(&self, painter: &mut ShapePainter, seconds: f32, params: &Bubbles) {
+    /// A new bubble just below the bottom of the canvas, or `None` if the mask turned every spot down.
+    /// `extent` is half the canvas size.
+    fn spawn(params: &Bubbles, extent: Vec2, rng: &mut impl Rng) -> Option<Self> {
+        let scale_range = 0.4;
+        let scale = rng.random::<f32>() * scale_range + (1.0 - (scale_range / 2.0));
+        let bottom = -extent.y - params.starting - (scale * params.starting_range);
+
+        let mut pos = Vec2::new((rng.random::<f32>() * 2.0 - 1.0) * extent.x, bottom);
+        if params.mask.is_loaded() {
+            // The mask is stretched over the canvas, from its bottom edge to the surface.
+            // Rather than put the bubble somewhere the mask excludes, leave it out.
+            let mut spot = None;
+            for _ in 0..100 {
+                let uv = Vec2::new(rng.random::<f32>(), rng.random::<f32>());
+                let darkness = 1.0 - params.mask.sample(uv).unwrap_or(0.0);
+                if rng.random::<f32>() < darkness {
+                    spot = Some(Vec2::new((uv.x * 2.0 - 1.0) * extent.x, (-extent.y).lerp(extent.y - params.surface, uv.y)));
+                    break;
+                }
+            }
+            pos = spot?;
+        }
+
+        Some(Self {
+            pos,
+            rise: 0.0,
+            age: 0.0,
+            wobble_offset: rng.random::<f32>(),
+            scale,
+            layer: rng.random_range(0..params.layers.max(1)),
+            popped: None,
+        })
+    }
+
+    fn draw(&self, painter: &mut Painter, seconds: f32, params: &Bubbles) {
         let pos = self.pos(seconds, params);
-        painter.set_translation(pos.extend(1.0));
+        let layer = self.layer(params);
+        painter.set_translation(pos.extend(BUBBLE_Z - layer as f32 * LAYER_SPACING));
         painter.hollow = true;
-        let r_1 = params.outer_radius_min.lerp(params.outer_radius_max, self.t(seconds)) * self.scale;
-        let r_2 = params.inner_radius_min.lerp(params.inner_radius_max, self.t(seconds)) * self.scale;
+        let r_1 = self.outer_radius(params);
+        let r_2 = params.inner_radius_min.lerp(params.inner_radius_max, self.t(params)) * self.scale * params.layer_scale(layer);
+
+        if let Some(popped) = self.popped {
+            self.draw_pop(painter, r_1, popped / params.pop_duration, params);
+            return;
+        }
+
+        if params.fill_alpha > 0.0 {
+            let alpha = painter.color.alpha();
+            painter.color.set_alpha(alpha * params.fill_alpha);
+            painter.hollow = false;
+            painter.circle(r_1);
+            painter.hollow = true;
+            painter.color.set_alpha(alpha);
+        }
 
         painter.thickness = params.thickness;
-        painter.circle(r_1);
+        if params.iridescent {
+            self.draw_iridescent_rim(painter, r_1, seconds, params);
+        } else {
+            painter.circle(r_1);
+        }
+
+        if layer > 0 {
+            // A wide faint halo around distant bubbles reads as being out of focus.
+            let alpha = painter.color.alpha();
+            painter.color.set_alpha(alpha * 0.3);
+            painter.thickness = params.thickness * (1.0 + layer as f32 * 2.0);
+            painter.circle(r_1);
+            painter.color.set_alpha(alpha);
+        }
 
         painter.thickness = params.shine_thickness;
         painter.arc(r_2, params.shine_start, params.shine_end);
+
+        // Fainter, shorter reflections around the rest of the bubble, starting opposite the main one.
+        let shine_length = params.shine_end - params.shine_start;
+        let alpha = painter.color.alpha();
+        for i in 1..params.highlights {
+            let falloff = 0.6_f32.powi(i as i32);
+            let start = params.shine_start + PI + (i - 1) as f32 * PI / params.highlights as f32;
+            painter.color.set_alpha(alpha * falloff);
+            painter.arc(r_2 * (1.0 - 0.05 * i as f32), start, start + shine_length * falloff);
+        }
+        painter.color.set_alpha(alpha);
+    }
+
+    /// The rim as a ring of short arcs, each tinted along a rainbow like light through a thin soap film.
+    fn draw_iridescent_rim(&self, painter: &mut Painter, radius: f32, seconds: f32, params: &Bubbles) {
+        const SEGMENTS: usize = 32;
+
+        let base = Srgba::from(painter.color);
+        let step = TAU / SEGMENTS as f32;
+        let cap = painter.cap;
+        painter.cap = Cap::None;
+        for i in 0..SEGMENTS {
+            let angle = i as f32 * step;
+            let hue = angle.to_degrees() + params.hue_shift + (seconds * params.hue_speed + self.wobble_offset) * 360.0;
+            let film = gamut_map(Oklcha::new(0.85, 0.15, hue.rem_euclid(360.0), base.alpha));
+            painter.set_color(base.mix_oklab(&film, params.iridescence));
+            // Overlap the segments a little so there are no gaps between them.
+            painter.arc(radius, angle, angle + step * 1.1);
+        }
+        painter.set_color(base);
+        painter.cap = cap;
     }
 
-    fn t(&self, seconds: f32) -> f32 {
-        let frac = (seconds % CYCLE) / CYCLE;
-        (frac + 1.0 + self.spawn_offset) % 1.0
+    /// A ring bursting outwards with droplets flung off it, `progress` running from 0 to 1.
+    fn draw_pop(&self, painter: &mut Painter, radius: f32, progress: f32, params: &Bubbles) {
+        const DROPLETS: usize = 7;
+
+        let fade = 1.0 - progress.clamp(0.0, 1.0);
+        let alpha = painter.color.alpha();
+        painter.color.set_alpha(alpha * fade);
+        painter.thickness = params.thickness * fade;
+        painter.circle(radius * (1.0 + progress * 0.6));
+
+        painter.hollow = false;
+        let start = painter.transform;
+        for i in 0..DROPLETS {
+            let angle = TAU * (i as f32 + self.wobble_offset) / DROPLETS as f32;
+            painter.translate((Vec2::from_angle(angle) * radius * (1.0 + progress * 1.5)).extend(0.0));
+            painter.circle(radius * 0.08 * fade);
+            painter.transform = start;
+        }
+    }
+
+    /// How far through its life the bubble is, from 0 to 1.
+    fn t(&self, params: &Bubbles) -> f32 {
+        (self.age / params.lifetime).clamp(0.0, 1.0)
+    }
+
+    fn outer_radius(&self, params: &Bubbles) -> f32 {
+        params.outer_radius_min.lerp(params.outer_radius_max, self.t(params)) * self.scale * params.layer_scale(self.layer(params))
+    }
+
+    /// The bubble's depth layer, kept in range if the number of layers has been lowered since it spawned.
+    fn layer(&self, params: &Bubbles) -> usize {
+        self.layer.min(params.layers.max(1) - 1)
     }
 
     fn pos(&self, seconds: f32, params: &Bubbles) -> Vec2 {
-        let t = self.t(seconds);
-        let x = self.x + (seconds + self.wobble_offset * params.wobble_frequency).sin() * params.wobble_size;
-        let start = Vec2::new(t, -params.starting - (self.scale * params.starting_range));
-        let end = Vec2::new(t, params.starting + (self.scale * params.starting_range));
-        let line = start.lerp(end, t);
-        Vec2 {
-            x: line.x + x,
-            y: line.y,
+        // Each bubble follows its own row of the noise.
+        let wobble = params.noise.simplex2(Vec2::new(seconds * params.wobble_frequency, self.wobble_offset * 1000.0)) * params.wobble_size;
+        self.pos + Vec2::X * wobble + params.drift(self.layer(params), seconds)
+    }
+}
+
+#[derive(Resource, Debug)]
+struct Water {
+    render: bool,
+    top_color: Srgba,
+    bottom_color: Srgba,
+    bands: usize,
+    caustics: bool,
+    caustic_count: usize,
+    /// How far down the caustics reach, as a fraction of the water's height.
+    caustic_depth: f32,
+    caustic_size: f32,
+    caustic_alpha: f32,
+    caustic_speed: f32,
+    particulate: bool,
+    particle_count: usize,
+    particle_size: f32,
+    particle_alpha: f32,
+    particle_drift: f32,
+}
+
+impl Default for Water {
+    fn default() -> Self {
+        Self {
+            render: true,
+            top_color: BLUE.pastel(),
+            bottom_color: NAVY.pastel(),
+            bands: 48,
+            caustics: true,
+            caustic_count: 60,
+            caustic_depth: 0.3,
+            caustic_size: 1.2,
+            caustic_alpha: 0.15,
+            caustic_speed: 0.6,
+            particulate: true,
+            particle_count: 150,
+            particle_size: 0.03,
+            particle_alpha: 0.4,
+            particle_drift: 0.2,
         }
     }
 }
 
-#[derive(Resource)]
+impl Water {
+    /// Fills a rectangle of half-size `extent` around the origin.
+    fn draw(&self, painter: &mut Painter, seconds: f32, extent: Vec2, rand: &Random) {
+        painter.set_rotation(Quat::IDENTITY);
+        painter.hollow = false;
+        painter.corner_radii = Vec4::ZERO;
+        self.draw_gradient(painter, extent);
+        if self.caustics {
+            self.draw_caustics(painter, seconds, extent, rand);
+        }
+        if self.particulate {
+            self.draw_particulate(painter, seconds, extent, rand);
+        }
+    }
+
+    /// Stacked bands from the surface colour at the top to the depths at the bottom.
+    fn draw_gradient(&self, painter: &mut Painter, extent: Vec2) {
+        let band_height = extent.y * 2.0 / self.bands as f32;
+        for band in 0..self.bands {
+            let t = (band as f32 + 0.5) / self.bands as f32;
+            let y = extent.y - band_height * (band as f32 + 0.5);
+            painter.set_color(self.top_color.mix_oklab(&self.bottom_color, t));
+            painter.set_translation(Vec3::new(0.0, y, WATER_Z));
+            // Overlap the bands slightly so no seams show between them.
+            painter.rect(Vec2::new(extent.x * 2.0, band_height * 1.05));
+        }
+    }
+
+    /// Wavering arcs and lines of focused light, fading out with depth.
+    fn draw_caustics(&self, painter: &mut Painter, seconds: f32, extent: Vec2, rand: &Random) {
+        let rand = rand.stream("caustics");
+        let t = seconds * self.caustic_speed;
+        let depth = extent.y * 2.0 * self.caustic_depth;
+        painter.hollow = true;
+        painter.cap = Cap::Round;
+        for i in 0..self.caustic_count {
+            let phase = rand.f32((i, 2)) * TAU;
+            let below = rand.f32((i, 1));
+            let center = Vec2::new(
+                rand.range((i, 0), -1.0..1.0) * extent.x + (t + phase).sin() * 0.5,
+                extent.y - below * depth,
+            );
+            let alpha = self.caustic_alpha * (1.0 - below);
+            painter.set_color(WHITE.with_alpha(alpha));
+            painter.set_translation(center.extend(WATER_Z + 0.01));
+            painter.thickness = 0.04 + 0.04 * (t * 1.3 + phase).sin().abs();
+
+            let radius = self.caustic_size * (0.6 + 0.4 * (t * 0.8 + phase).sin());
+            let start = phase + t * rand.range((i, 3), -0.5..0.5);
+            painter.arc(radius, start, start + PI * (0.4 + 0.3 * (t + phase).cos()));
+
+            // Link to the next caustic with a thin line so they form a web.
+            let next = (i + 1) % self.caustic_count.max(1);
+            let to = Vec2::new(
+                rand.range((next, 0), -1.0..1.0) * extent.x + (t + rand.f32((next, 2)) * TAU).sin() * 0.5,
+                extent.y - rand.f32((next, 1)) * depth,
+            );
+            if center.distance(to) < self.caustic_size * 3.0 {
+                painter.thickness *= 0.5;
+                painter.line(Vec3::ZERO, (to - center).extend(0.0));
+            }
+        }
+    }
+
+    /// Specks suspended in the water, slowly sinking and swaying.
+    fn draw_particulate(&self, painter: &mut Painter, seconds: f32, extent: Vec2, rand: &Random) {
+        let rand = rand.stream("particulate");
+        painter.hollow = false;
+        painter.set_color(WHITE.with_alpha(self.particle_alpha));
+        let height = extent.y * 2.0;
+        for i in 0..self.particle_count {
+            let phase = rand.f32((i, 2)) * TAU;
+            let sink = seconds * self.particle_drift * rand.range((i, 3), 0.5..1.5);
+            let x = rand.range((i, 0), -1.0..1.0) * extent.x + (seconds * 0.3 + phase).sin() * 0.3;
+            let y = extent.y - (rand.f32((i, 1)) * height + sink).rem_euclid(height);
+            painter.set_translation(Vec3::new(x, y, WATER_Z + 0.02));
+            painter.circle(self.particle_size * rand.range((i, 4), 0.5..1.5));
+        }
+    }
+}
+
+#[derive(Resource, Debug)]
 struct Rays {
-    rays: Vec<Ray>,
+    count: usize,
     origin: Vec2,
     render: bool,
     angle: f32,
+    /// Angle between the first and last ray, fanning them out from the origin.
+    spread: f32,
+    tint: Srgba,
     min_length: f32,
     max_length: f32,
     thickness: f32,
@@ -220,20 +834,27 @@ struct Rays {
 }
 
 impl Rays {
-    fn draw(&self, painter: &mut ShapePainter, seconds: f32) {
-        for ray in &self.rays {
-            ray.draw(painter, seconds, self);
-        }
+    fn draw_handle(&self, painter: &mut Painter) {
+        painter.set_translation(self.origin.extend(RAY_Z));
+        painter.set_color(self.tint.with_alpha(0.6));
+        painter.hollow = true;
+        painter.thickness = 0.05;
+        painter.circle(ORIGIN_HANDLE);
     }
 }
 
-impl Default for Rays {
-    fn default() -> Self {
+#[derive(Resource, Default)]
+struct GeneratedRays {
+    rays: Vec<Ray>,
+}
+
+impl GeneratedRays {
+    fn generate(count: usize) -> Self {
         let mut rays = Vec::new();
         let mut rng = rand::rng();
 
         let mut acc: f32 = 0.0;
-        for _ in 0..60 {
+        for _ in 0..count {
             let thickness = rng.random::<f32>();
             let length = rng.random::<f32>();
             let offset = rng.random::<f32>();
@@ -246,13 +867,32 @@ impl Default for Rays {
                 offset,
                 frequency,
                 x,
+                fan: 0.0,
             })
         }
 
+        for ray in &mut rays {
+            ray.fan = ray.x / acc.max(f32::EPSILON) - 0.5;
+        }
+
+        Self { rays }
+    }
+
+    fn draw(&self, painter: &mut Painter, params: &Rays, seconds: f32) {
+        for ray in &self.rays {
+            ray.draw(painter, seconds, params);
+        }
+    }
+}
+
+impl Default for Rays {
+    fn default() -> Self {
         Self {
-            rays,
-            origin: Vec2::new(-6.5, 2.5),
+            count: 60,
+            origin: Vec2::new(-15.0, 6.0),
             angle: 5.1,
+            spread: 0.0,
+            tint: YELLOW.pastel_very(),
             render: true,
             min_length: 0.5,
             max_length: 5.5,
@@ -269,20 +909,24 @@ struct Ray {
     thickness: f32,
     offset: f32,
     frequency: f32,
+    /// Position across the band of rays, from -0.5 to 0.5.
+    fan: f32,
 }
 
 impl Ray {
-    fn draw(&self, painter: &mut ShapePainter, seconds: f32, params: &Rays) {
+    fn draw(&self, painter: &mut Painter, seconds: f32, params: &Rays) {
         let origin = params.origin;
-        painter.set_translation(origin.extend(2.0));
+        painter.set_translation(origin.extend(RAY_Z));
         painter.thickness = params.thickness * self.thickness;
 
         let alpha = params.alpha * f32::sin(self.frequency * (seconds - self.offset) * params.speed);
-        painter.set_color(YELLOW.pastel_very().with_alpha(alpha));
+        painter.set_color(params.tint.with_alpha(alpha));
 
+        // The painter is already at the origin, so the line is relative to it.
         let length = (params.max_length - params.min_length) * self.length + params.min_length;
-        let start = origin + Vec2::new(self.x * params.thickness, 0.0);
-        let end = start + Vec2::new(length * params.angle.cos(), length * params.angle.sin());
-        painter.line(start.extend(2.0), end.extend(2.0));
+        let angle = params.angle + self.fan * params.spread;
+        let start = Vec2::new(self.x * params.thickness, 0.0);
+        let end = start + Vec2::from_angle(angle) * length;
+        painter.line(start.extend(0.0), end.extend(0.0));
     }
 }
@@ -0,0 +1,276 @@
+use bevy::app::App;
+use bevy::input::mouse::{AccumulatedMouseMotion, AccumulatedMouseScroll, MouseScrollUnit};
+use bevy::prelude::*;
+use bevy::render::camera::{ScalingMode, Viewport};
+use bevy_egui::{egui, EguiContextPass, EguiContexts};
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+use crate::{ProgramState, UIState};
+
+/// Keeps the camera framing a fixed area of the world, whatever the window size.
+pub struct CanvasPlugin;
+
+impl Plugin for CanvasPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<Canvas>()
+            .init_resource::<CameraView>()
+            .add_systems(EguiContextPass, params_ui.run_if(not(in_state(ProgramState::MainMenu))))
+            .add_systems(Update, navigate.run_if(not(in_state(ProgramState::MainMenu))))
+            .add_systems(PostUpdate, fit_camera.before(TransformSystem::TransformPropagate))
+        ;
+    }
+}
+
+/// How far back the orthographic camera sits. Far enough to be in front of everything drawn.
+const ORTHOGRAPHIC_DISTANCE: f32 = 100.0;
+/// Zoom factor for one line of scrolling.
+const ZOOM_STEP: f32 = 1.1;
+
+/// Marks the camera that the canvas is fitted to.
+#[derive(Component)]
+pub struct CanvasCamera;
+
+/// The area every piece draws into, centered on the origin in the z=0 plane.
+#[derive(Resource)]
+pub struct Canvas {
+    /// Width over height.
+    pub aspect: f32,
+    /// Height in world units.
+    pub height: f32,
+    pub fit: Fit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter)]
+pub enum Fit {
+    /// Show the whole canvas, with bars where the window's shape differs.
+    Letterbox,
+    /// Fill the window, cutting off whatever part of the canvas doesn't fit.
+    Crop,
+}
+
+impl Default for Canvas {
+    fn default() -> Self {
+        Self {
+            aspect: 16.0 / 9.0,
+            // Matches what the original camera, 16 units back with a 45 degree view, could see.
+            height: 13.25,
+            fit: Fit::Letterbox,
+        }
+    }
+}
+
+impl Canvas {
+    pub fn size(&self) -> Vec2 {
+        Vec2::new(self.height * self.aspect, self.height)
+    }
+
+    pub fn half_size(&self) -> Vec2 {
+        self.size() / 2.0
+    }
+
+    /// World height a target of `target_aspect` has to show for the canvas to fit into it.
+    pub fn visible_height(&self, target_aspect: f32) -> f32 {
+        match self.fit {
+            Fit::Crop if target_aspect > self.aspect => self.height * self.aspect / target_aspect,
+            Fit::Crop => self.height,
+            Fit::Letterbox if target_aspect < self.aspect => self.height * self.aspect / target_aspect,
+            Fit::Letterbox => self.height,
+        }
+    }
+
+    /// The part of a window of `window_size` physical pixels to render into, or `None` for all of it.
+    pub fn viewport(&self, window_size: UVec2) -> Option<Viewport> {
+        if self.fit == Fit::Crop || window_size.x == 0 || window_size.y == 0 {
+            return None;
+        }
+
+        let window = window_size.as_vec2();
+        let size = if window.x / window.y > self.aspect {
+            Vec2::new(window.y * self.aspect, window.y)
+        } else {
+            Vec2::new(window.x, window.x / self.aspect)
+        };
+        let size = size.round().as_uvec2().max(UVec2::ONE);
+
+        Some(Viewport {
+            physical_position: (window_size - size) / 2,
+            physical_size: size,
+            ..default()
+        })
+    }
+}
+
+/// Where the user has panned and zoomed to, on top of the canvas framing.
+#[derive(Resource)]
+pub struct CameraView {
+    /// Use an orthographic projection, so depth doesn't change the size of things.
+    pub orthographic: bool,
+    pub pan: Vec2,
+    pub zoom: f32,
+}
+
+impl Default for CameraView {
+    fn default() -> Self {
+        Self {
+            orthographic: false,
+            pan: Vec2::ZERO,
+            zoom: 1.0,
+        }
+    }
+}
+
+impl CameraView {
+    /// Switches `projection` to the chosen kind, showing `height` of the z=0 plane at the current zoom.
+    /// Returns how far back from the plane the camera should be.
+    pub fn camera_distance(&self, projection: &mut Projection, height: f32) -> f32 {
+        if self.orthographic {
+            let scaling_mode = ScalingMode::FixedVertical { viewport_height: height / self.zoom };
+            match projection {
+                Projection::Orthographic(orthographic) => orthographic.scaling_mode = scaling_mode,
+                projection => *projection = Projection::Orthographic(OrthographicProjection {
+                    scaling_mode,
+                    ..OrthographicProjection::default_3d()
+                }),
+            }
+            return ORTHOGRAPHIC_DISTANCE;
+        }
+
+        // Perspective zooms by narrowing the field of view rather than moving in,
+        // so the camera never ends up among the things it's looking at.
+        let distance = perspective_distance(&PerspectiveProjection::default(), height);
+        let fov = 2.0 * (height / self.zoom / 2.0 / distance).atan();
+        match projection {
+            Projection::Perspective(perspective) => perspective.fov = fov,
+            projection => *projection = Projection::Perspective(PerspectiveProjection {
+                fov,
+                ..PerspectiveProjection::default()
+            }),
+        }
+        distance
+    }
+}
+
+/// Distance from the z=0 plane a perspective camera needs to be to see `height` of it.
+pub fn perspective_distance(projection: &PerspectiveProjection, height: f32) -> f32 {
+    height / 2.0 / (projection.fov / 2.0).tan()
+}
+
+/// Drag with the right or middle mouse button to pan, scroll to zoom, `R` to reset and `P` to switch projection.
+fn navigate(
+    mut contexts: EguiContexts,
+    mut view: ResMut<CameraView>,
+    canvas: Res<Canvas>,
+    keys: Res<ButtonInput<KeyCode>>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    motion: Res<AccumulatedMouseMotion>,
+    scroll: Res<AccumulatedMouseScroll>,
+    cameras: Query<&Camera, With<CanvasCamera>>,
+) {
+    let ctx = contexts.ctx_mut();
+    if !ctx.wants_keyboard_input() {
+        if keys.just_pressed(KeyCode::KeyR) {
+            view.pan = Vec2::ZERO;
+            view.zoom = 1.0;
+        }
+        if keys.just_pressed(KeyCode::KeyP) {
+            view.orthographic = !view.orthographic;
+        }
+    }
+    if ctx.wants_pointer_input() || ctx.is_pointer_over_area() {
+        return;
+    }
+
+    let lines = match scroll.unit {
+        MouseScrollUnit::Line => scroll.delta.y,
+        MouseScrollUnit::Pixel => scroll.delta.y / 100.0,
+    };
+    if lines != 0.0 {
+        view.zoom = (view.zoom * ZOOM_STEP.powf(lines)).clamp(0.05, 50.0);
+    }
+
+    if buttons.pressed(MouseButton::Right) || buttons.pressed(MouseButton::Middle) {
+        let Ok(camera) = cameras.single() else {
+            return;
+        };
+        let Some(viewport) = camera.logical_viewport_size() else {
+            return;
+        };
+        // Pan in the z=0 plane, so the canvas moves along with the cursor.
+        let world_per_pixel = canvas.visible_height(viewport.x / viewport.y) / view.zoom / viewport.y;
+        view.pan += Vec2::new(-motion.delta.x, motion.delta.y) * world_per_pixel;
+    }
+}
+
+fn fit_camera(
+    canvas: Res<Canvas>,
+    view: Res<CameraView>,
+    windows: Query<&Window>,
+    mut cameras: Query<(&mut Camera, &mut Transform, &mut Projection), With<CanvasCamera>>,
+) {
+    let Ok(window) = windows.single() else {
+        return;
+    };
+    let window_size = window.physical_size();
+    if window_size.x == 0 || window_size.y == 0 {
+        return;
+    }
+
+    for (mut camera, mut transform, mut projection) in &mut cameras {
+        let viewport = canvas.viewport(window_size);
+        let target_aspect = viewport.as_ref()
+            .map_or(window_size.as_vec2(), |viewport| viewport.physical_size.as_vec2());
+        let height = canvas.visible_height(target_aspect.x / target_aspect.y);
+
+        if camera.viewport != viewport {
+            camera.viewport = viewport;
+        }
+        transform.translation = view.pan.extend(view.camera_distance(&mut projection, height));
+    }
+}
+
+fn params_ui(
+    mut contexts: EguiContexts,
+    ui_state: Res<UIState>,
+    mut canvas: ResMut<Canvas>,
+    mut view: ResMut<CameraView>,
+) {
+    if !ui_state.params_panel {
+        return;
+    }
+
+    egui::Window::new("Canvas").show(contexts.ctx_mut(), |ui| {
+        ui.heading("Aspect");
+        ui.horizontal_wrapped(|ui| {
+            for (name, aspect) in [
+                ("16:9", 16.0 / 9.0),
+                ("3:2", 3.0 / 2.0),
+                ("4:3", 4.0 / 3.0),
+                ("1:1", 1.0),
+                ("A-series", 2.0_f32.sqrt()),
+                ("9:16", 9.0 / 16.0),
+            ] {
+                if ui.selectable_label((canvas.aspect - aspect).abs() < 0.001, name).clicked() {
+                    canvas.aspect = aspect;
+                }
+            }
+        });
+        ui.add(egui::Slider::new(&mut canvas.aspect, 0.25..=4.0).text("Aspect"));
+        ui.add(egui::Slider::new(&mut canvas.height, 1.0..=60.0).text("Height"));
+
+        ui.heading("Fit");
+        ui.horizontal(|ui| {
+            for fit in Fit::iter() {
+                ui.radio_value(&mut canvas.fit, fit, format!("{:?}", fit));
+            }
+        });
+
+        ui.heading("View");
+        ui.checkbox(&mut view.orthographic, "Orthographic (P)");
+        ui.add(egui::Slider::new(&mut view.zoom, 0.05..=50.0).logarithmic(true).text("Zoom"));
+        if ui.button("Reset (R)").clicked() {
+            view.pan = Vec2::ZERO;
+            view.zoom = 1.0;
+        }
+    });
+}
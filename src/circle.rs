@@ -1,6 +1,6 @@
 use bevy::app::App;
 use bevy::prelude::*;
-use bevy_vector_shapes::prelude::*;
+use crate::recording::Painter;
 use crate::ProgramState;
 
 #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
@@ -24,7 +24,7 @@ impl Plugin for CircleArt {
     }
 }
 
-fn draw(mut painter: ShapePainter) {
+fn draw(mut painter: Painter) {
     // Draw a circle
     painter.circle(1.0);
 }
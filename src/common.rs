@@ -1,54 +1,303 @@
-use bevy::color::Srgba;
-use bevy::prelude::{Alpha, Resource};
+pub mod noise;
 
+use std::collections::HashMap;
+use std::f32::consts::TAU;
+use std::ops::Range;
+use bevy::color::{Hue, Mix, Oklaba, Oklcha, Srgba};
+use bevy::math::{IVec2, Vec2};
+use bevy::color::ColorToPacked;
+use bevy::prelude::{Alpha, Camera, GlobalTransform, InfinitePlane3d, Resource, Vec3, Window};
+use bevy_egui::egui;
+
+/// Color adjustments done in Oklch, so lightness, chroma and hue can each change without
+/// dragging the others along. Results are mapped back into the sRGB gamut.
 pub trait Modifier {
+    /// Softened towards white and made opaque.
     fn pastel(&self) -> Srgba;
     fn pastel_very(&self) -> Srgba;
+    /// Moves lightness `amount` of the way towards white.
+    fn lighten(&self, amount: f32) -> Srgba;
+    /// Moves lightness `amount` of the way towards black.
+    fn darken(&self, amount: f32) -> Srgba;
+    /// Multiplies lightness, in place of multiplying the channels.
+    fn scale_lightness(&self, factor: f32) -> Srgba;
+    /// Scales chroma up by `amount`, so 1.0 doubles it.
+    fn saturate(&self, amount: f32) -> Srgba;
+    /// Scales chroma down by `amount`, so 1.0 leaves gray.
+    fn desaturate(&self, amount: f32) -> Srgba;
+    fn hue_rotate(&self, degrees: f32) -> Srgba;
+    /// Blends `t` of the way to `other` in Oklab, which keeps the midpoints from going muddy.
+    fn mix_oklab(&self, other: &Srgba, t: f32) -> Srgba;
 }
 
-
 impl Modifier for Srgba {
     fn pastel(&self) -> Srgba {
-        let mut out = *self + Srgba::WHITE * 0.25;
-        out.set_alpha(1.0);
-        out
+        self.mix_oklab(&Srgba::WHITE, 0.25).with_alpha(1.0)
     }
 
     fn pastel_very(&self) -> Srgba {
-        let mut out = *self + Srgba::WHITE * 0.4;
-        out.set_alpha(1.0);
-        out
+        self.mix_oklab(&Srgba::WHITE, 0.4).with_alpha(1.0)
+    }
+
+    fn lighten(&self, amount: f32) -> Srgba {
+        let color = Oklcha::from(*self);
+        gamut_map(color.with_lightness(color.lightness + (1.0 - color.lightness) * amount))
+    }
+
+    fn darken(&self, amount: f32) -> Srgba {
+        let color = Oklcha::from(*self);
+        gamut_map(color.with_lightness(color.lightness * (1.0 - amount)))
+    }
+
+    fn scale_lightness(&self, factor: f32) -> Srgba {
+        let color = Oklcha::from(*self);
+        gamut_map(color.with_lightness(color.lightness * factor))
+    }
+
+    fn saturate(&self, amount: f32) -> Srgba {
+        let color = Oklcha::from(*self);
+        gamut_map(color.with_chroma(color.chroma * (1.0 + amount)))
+    }
+
+    fn desaturate(&self, amount: f32) -> Srgba {
+        let color = Oklcha::from(*self);
+        gamut_map(color.with_chroma(color.chroma * (1.0 - amount).max(0.0)))
+    }
+
+    fn hue_rotate(&self, degrees: f32) -> Srgba {
+        let color = Oklcha::from(*self);
+        gamut_map(color.with_hue((color.hue + degrees).rem_euclid(360.0)))
+    }
+
+    fn mix_oklab(&self, other: &Srgba, t: f32) -> Srgba {
+        let mixed = Oklaba::from(*self).mix(&Oklaba::from(*other), t);
+        gamut_map(Oklcha::from(mixed))
+    }
+}
+
+/// Brings an Oklch color into sRGB by lowering its chroma until it fits, keeping lightness and hue.
+pub fn gamut_map(color: Oklcha) -> Srgba {
+    const EPSILON: f32 = 1e-4;
+    let in_gamut = |color: Srgba| {
+        [color.red, color.green, color.blue].iter().all(|channel| (-EPSILON..=1.0 + EPSILON).contains(channel))
+    };
+    let clamp = |color: Srgba| Srgba::new(
+        color.red.clamp(0.0, 1.0),
+        color.green.clamp(0.0, 1.0),
+        color.blue.clamp(0.0, 1.0),
+        color.alpha,
+    );
+
+    let color = color.with_lightness(color.lightness.clamp(0.0, 1.0));
+    let srgba = Srgba::from(color);
+    if in_gamut(srgba) {
+        return clamp(srgba);
     }
+
+    let (mut low, mut high) = (0.0, color.chroma);
+    for _ in 0..16 {
+        let mid = (low + high) / 2.0;
+        if in_gamut(Srgba::from(color.with_chroma(mid))) {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    clamp(Srgba::from(color.with_chroma(low)))
+}
+
+/// A labelled colour picker for an `Srgba`. Returns true if the colour changed.
+pub fn color_edit(ui: &mut egui::Ui, label: &str, color: &mut Srgba) -> bool {
+    let [r, g, b, a] = color.to_u8_array();
+    let mut srgb = [r, g, b];
+    let changed = ui.horizontal(|ui| {
+        let changed = ui.color_edit_button_srgb(&mut srgb).changed();
+        ui.label(label);
+        changed
+    }).inner;
+
+    if changed {
+        *color = Srgba::rgba_u8(srgb[0], srgb[1], srgb[2], a);
+    }
+    changed
+}
+
+/// A seed picked at random. Seeds are kept within `u32` so they survive being typed into a `DragValue`, which goes through `f64`.
+pub fn random_seed() -> u64 {
+    u64::from(rand::random::<u32>())
+}
+
+/// A seed field with a button to roll a new one. Returns true if the seed changed.
+pub fn seed_edit(ui: &mut egui::Ui, seed: &mut u64) -> bool {
+    ui.horizontal(|ui| {
+        let mut changed = ui.add(egui::DragValue::new(seed).range(0..=u64::from(u32::MAX)).prefix("Seed ")).changed();
+        if ui.button("Reroll").clicked() {
+            *seed = random_seed();
+            changed = true;
+        }
+        changed
+    }).inner
 }
 
-#[derive(Resource)]
-pub struct CachedRandom {
-    size: usize,
-    floats: Vec<f32>,
+/// Projects the cursor through `camera` onto the flat plane at depth `z`, in world units.
+pub fn cursor_to_plane(window: &Window, camera: &Camera, camera_transform: &GlobalTransform, z: f32) -> Option<Vec2> {
+    let cursor = window.cursor_position()?;
+    let ray = camera.viewport_to_world(camera_transform, cursor).ok()?;
+    let distance = ray.intersect_plane(Vec3::Z * z, InfinitePlane3d::new(Vec3::Z))?;
+    Some(ray.get_point(distance).truncate())
 }
 
-impl Default for CachedRandom {
+/// Random numbers looked up by key rather than drawn in turn. Each value is a hash of the seed and
+/// the key, so values can be read in any order, every frame, and the same key always gives the same value.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct Random {
+    seed: u64,
+}
+
+impl Default for Random {
     fn default() -> Self {
-        Self::new(1031) // A prime so there won't be loops for a long time.
+        Self::new(random_seed())
     }
 }
 
-impl CachedRandom {
-    pub fn new(size: usize) -> Self {
-        let mut floats = Vec::with_capacity(size);
+// Not every kind of value has a piece using it yet.
+#[allow(dead_code)]
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    /// The seed every value follows from, so a frame can be noted down and made again.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
 
-        for _ in 0..size {
-            floats.push(rand::random::<f32>());
-        }
+    /// Seed controls. Returns true if the seed changed.
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> bool {
+        seed_edit(ui, &mut self.seed)
+    }
+
+    /// An independent set of values for one use, so the keys one use picks can't line up with another's.
+    pub fn stream(&self, name: &str) -> Self {
+        Self::new(name.bytes().fold(mix(self.seed), |hash, byte| mix(hash ^ byte as u64)))
+    }
+
+    pub fn u64(&self, key: impl Key) -> u64 {
+        mix(key.mix_into(self.seed))
+    }
+
+    pub fn u32(&self, key: impl Key) -> u32 {
+        (self.u64(key) >> 32) as u32
+    }
+
+    /// A whole number in `0..count`.
+    pub fn below(&self, key: impl Key, count: u32) -> u32 {
+        ((self.u32(key) as u64 * count as u64) >> 32) as u32
+    }
+
+    /// In `0..1`.
+    pub fn f32(&self, key: impl Key) -> f32 {
+        (self.u64(key) >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    pub fn range(&self, key: impl Key, range: Range<f32>) -> f32 {
+        range.start + (range.end - range.start) * self.f32(key)
+    }
+
+    /// Each component in `0..1`.
+    pub fn vec2(&self, key: impl Key) -> Vec2 {
+        Vec2::new(self.f32((key, 0)), self.f32((key, 1)))
+    }
+
+    /// A point on the unit circle, at an even chance of any angle.
+    pub fn unit_circle(&self, key: impl Key) -> Vec2 {
+        Vec2::from_angle(self.f32(key) * TAU)
+    }
+
+    /// Normally distributed, with a mean of 0 and a standard deviation of 1.
+    pub fn gaussian(&self, key: impl Key) -> f32 {
+        // Box-Muller. The first value is kept off zero so its log is finite.
+        let u = 1.0 - self.f32((key, 0));
+        let v = self.f32((key, 1));
+        (-2.0 * u.ln()).sqrt() * (TAU * v).cos()
+    }
+}
+
+/// Anything `Random` can look values up by: integers, or tuples of them for values that need more than one index.
+pub trait Key: Copy {
+    fn mix_into(self, hash: u64) -> u64;
+}
 
+macro_rules! integer_key {
+    ($($int:ty),*) => {
+        $(impl Key for $int {
+            fn mix_into(self, hash: u64) -> u64 {
+                mix(hash ^ self as u64)
+            }
+        })*
+    };
+}
+
+integer_key!(usize, u32, u64, i32);
+
+impl<A: Key, B: Key> Key for (A, B) {
+    fn mix_into(self, hash: u64) -> u64 {
+        self.1.mix_into(self.0.mix_into(hash))
+    }
+}
+
+impl<A: Key, B: Key, C: Key> Key for (A, B, C) {
+    fn mix_into(self, hash: u64) -> u64 {
+        self.2.mix_into(self.1.mix_into(self.0.mix_into(hash)))
+    }
+}
+
+impl Key for IVec2 {
+    fn mix_into(self, hash: u64) -> u64 {
+        (self.x, self.y).mix_into(hash)
+    }
+}
+
+/// The SplitMix64 finalizer: scrambles every bit of the input into every bit of the output.
+fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// Buckets points into square cells so nearby points can be found without checking every pair.
+pub struct SpatialGrid {
+    cell_size: f32,
+    cells: HashMap<IVec2, Vec<usize>>,
+}
+
+impl SpatialGrid {
+    /// `cell_size` should be at least the largest distance that will be queried.
+    pub fn new(cell_size: f32) -> Self {
         Self {
-            size,
-            floats,
+            cell_size: cell_size.max(f32::EPSILON),
+            cells: HashMap::new(),
         }
     }
 
-    pub fn f32(&self, idx: usize) -> f32 {
-        let idx = idx % self.size;
-        self.floats[idx]
+    pub fn insert(&mut self, idx: usize, pos: Vec2) {
+        let cell = self.cell(pos);
+        self.cells.entry(cell).or_default().push(idx);
+    }
+
+    /// Everything in the cell containing `pos` and the eight around it.
+    /// Callers still need to check the actual distance.
+    pub fn nearby(&self, pos: Vec2) -> impl Iterator<Item = usize> + '_ {
+        let center = self.cell(pos);
+        (-1..=1)
+            .flat_map(move |x| (-1..=1).map(move |y| center + IVec2::new(x, y)))
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+    }
+
+    fn cell(&self, pos: Vec2) -> IVec2 {
+        (pos / self.cell_size).floor().as_ivec2()
     }
 }
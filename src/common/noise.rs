@@ -0,0 +1,325 @@
+#![allow(dead_code)]
+use std::fmt;
+use std::ops::{Add, Mul};
+use bevy::math::{IVec2, IVec3, IVec4, Vec2, Vec3, Vec4};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+const F2: f32 = 0.366_025_4; // (sqrt(3) - 1) / 2
+const G2: f32 = 0.211_324_9; // (3 - sqrt(3)) / 6
+const F3: f32 = 1.0 / 3.0;
+const G3: f32 = 1.0 / 6.0;
+const F4: f32 = 0.309_017; // (sqrt(5) - 1) / 4
+const G4: f32 = 0.138_196_6; // (5 - sqrt(5)) / 20
+/// Step for the finite differences behind curl noise.
+const CURL_EPSILON: f32 = 1e-3;
+
+const GRAD2: [Vec2; 8] = [
+    Vec2::new(1.0, 1.0),
+    Vec2::new(-1.0, 1.0),
+    Vec2::new(1.0, -1.0),
+    Vec2::new(-1.0, -1.0),
+    Vec2::new(1.0, 0.0),
+    Vec2::new(-1.0, 0.0),
+    Vec2::new(0.0, 1.0),
+    Vec2::new(0.0, -1.0),
+];
+
+/// Smooth pseudo-random fields. Every kind of noise is built on one shuffled table,
+/// so the same seed always gives the same fields. Values are roughly in -1..1.
+#[derive(Clone)]
+pub struct Noise {
+    seed: u64,
+    /// A shuffle of 0..256, repeated so lookups can add a coordinate without wrapping.
+    perm: [u8; 512],
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+/// Just the seed, since the table follows from it.
+impl fmt::Debug for Noise {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Noise")
+            .field("seed", &self.seed)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Noise {
+    pub fn new(seed: u64) -> Self {
+        let mut table: Vec<u8> = (0..=255).collect();
+        table.shuffle(&mut StdRng::seed_from_u64(seed));
+        let mut perm = [0; 512];
+        for (idx, value) in perm.iter_mut().enumerate() {
+            *value = table[idx % 256];
+        }
+        Self { seed, perm }
+    }
+
+    /// Random values at whole coordinates, smoothly blended between.
+    pub fn value2(&self, p: Vec2) -> f32 {
+        let cell = p.floor();
+        let base = cell.as_ivec2();
+        let mut corners = [0.0; 4];
+        for (corner, value) in corners.iter_mut().enumerate() {
+            *value = self.lattice_value(&(base + corner_offset(corner).truncate().truncate()).to_array());
+        }
+        blend(&mut corners, &fades(&(p - cell).to_array()))
+    }
+
+    pub fn value3(&self, p: Vec3) -> f32 {
+        let cell = p.floor();
+        let base = cell.as_ivec3();
+        let mut corners = [0.0; 8];
+        for (corner, value) in corners.iter_mut().enumerate() {
+            *value = self.lattice_value(&(base + corner_offset(corner).truncate()).to_array());
+        }
+        blend(&mut corners, &fades(&(p - cell).to_array()))
+    }
+
+    /// Random gradients at whole coordinates, smoothly blended between.
+    pub fn perlin2(&self, p: Vec2) -> f32 {
+        let cell = p.floor();
+        let (base, local) = (cell.as_ivec2(), p - cell);
+        let mut corners = [0.0; 4];
+        for (corner, value) in corners.iter_mut().enumerate() {
+            let offset = corner_offset(corner).truncate().truncate();
+            *value = grad2(self.hash(&(base + offset).to_array()), local - offset.as_vec2());
+        }
+        blend(&mut corners, &fades(&local.to_array()))
+    }
+
+    pub fn perlin3(&self, p: Vec3) -> f32 {
+        let cell = p.floor();
+        let (base, local) = (cell.as_ivec3(), p - cell);
+        let mut corners = [0.0; 8];
+        for (corner, value) in corners.iter_mut().enumerate() {
+            let offset = corner_offset(corner).truncate();
+            *value = grad3(self.hash(&(base + offset).to_array()), local - offset.as_vec3());
+        }
+        blend(&mut corners, &fades(&local.to_array()))
+    }
+
+    pub fn perlin4(&self, p: Vec4) -> f32 {
+        let cell = p.floor();
+        let (base, local) = (cell.as_ivec4(), p - cell);
+        let mut corners = [0.0; 16];
+        for (corner, value) in corners.iter_mut().enumerate() {
+            let offset = corner_offset(corner);
+            *value = grad4(self.hash(&(base + offset).to_array()), local - offset.as_vec4());
+        }
+        blend(&mut corners, &fades(&local.to_array()))
+    }
+
+    /// Gradient noise over a grid of triangles rather than squares, so it has no axis-aligned streaks
+    /// and each sample only looks at three corners.
+    pub fn simplex2(&self, p: Vec2) -> f32 {
+        let cell = (p + (p.x + p.y) * F2).floor();
+        let d0 = p - (cell - (cell.x + cell.y) * G2);
+        let step = if d0.x > d0.y { IVec2::X } else { IVec2::Y };
+
+        let base = cell.as_ivec2();
+        let corners = [
+            (base, d0),
+            (base + step, d0 - step.as_vec2() + G2),
+            (base + IVec2::ONE, d0 - 1.0 + 2.0 * G2),
+        ];
+        70.0 * corners.iter()
+            .map(|(corner, d)| falloff(0.5, d.length_squared()) * grad2(self.hash(&corner.to_array()), *d))
+            .sum::<f32>()
+    }
+
+    pub fn simplex3(&self, p: Vec3) -> f32 {
+        let cell = (p + (p.x + p.y + p.z) * F3).floor();
+        let d0 = p - (cell - (cell.x + cell.y + cell.z) * G3);
+
+        // The simplex the point is in follows from which of its coordinates are largest.
+        let rank = ranks(&d0.to_array());
+        let step = |k: i32| IVec3::from_array(rank.map(|r| (r >= 3 - k) as i32));
+
+        let base = cell.as_ivec3();
+        let mut total = 0.0;
+        for k in 0..4 {
+            let offset = if k == 3 { IVec3::ONE } else { step(k) };
+            let d = d0 - offset.as_vec3() + k as f32 * G3;
+            total += falloff(0.6, d.length_squared()) * grad3(self.hash(&(base + offset).to_array()), d);
+        }
+        32.0 * total
+    }
+
+    pub fn simplex4(&self, p: Vec4) -> f32 {
+        let cell = (p + p.element_sum() * F4).floor();
+        let d0 = p - (cell - cell.element_sum() * G4);
+
+        let rank = ranks(&d0.to_array());
+        let step = |k: i32| IVec4::from_array(rank.map(|r| (r >= 4 - k) as i32));
+
+        let base = cell.as_ivec4();
+        let mut total = 0.0;
+        for k in 0..5 {
+            let offset = if k == 4 { IVec4::ONE } else { step(k) };
+            let d = d0 - offset.as_vec4() + k as f32 * G4;
+            total += falloff(0.6, d.length_squared()) * grad4(self.hash(&(base + offset).to_array()), d);
+        }
+        27.0 * total
+    }
+
+    /// Samples fBm after pushing `p` around by two more fBm fields, for marbled, swirling patterns.
+    pub fn warp2(&self, p: Vec2, strength: f32, fractal: &Fractal) -> f32 {
+        let noise = |p| self.simplex2(p);
+        let offset = Vec2::new(fractal.fbm(p, noise), fractal.fbm(p + Vec2::new(5.2, 1.3), noise));
+        fractal.fbm(p + offset * strength, noise)
+    }
+
+    pub fn warp3(&self, p: Vec3, strength: f32, fractal: &Fractal) -> f32 {
+        let noise = |p| self.simplex3(p);
+        let offset = Vec3::new(
+            fractal.fbm(p, noise),
+            fractal.fbm(p + Vec3::new(5.2, 1.3, 2.8), noise),
+            fractal.fbm(p + Vec3::new(1.7, 9.2, 4.1), noise),
+        );
+        fractal.fbm(p + offset * strength, noise)
+    }
+
+    /// A swirling flow with no sources or sinks, so things carried along it neither bunch up nor spread out.
+    /// It's simplex noise's gradient turned a quarter turn.
+    pub fn curl2(&self, p: Vec2) -> Vec2 {
+        let dx = (self.simplex2(p + Vec2::X * CURL_EPSILON) - self.simplex2(p - Vec2::X * CURL_EPSILON)) / (2.0 * CURL_EPSILON);
+        let dy = (self.simplex2(p + Vec2::Y * CURL_EPSILON) - self.simplex2(p - Vec2::Y * CURL_EPSILON)) / (2.0 * CURL_EPSILON);
+        Vec2::new(dy, -dx)
+    }
+
+    /// The 3D flow is the curl of three simplex fields, offset from each other so they don't line up.
+    pub fn curl3(&self, p: Vec3) -> Vec3 {
+        let potential = |p: Vec3| Vec3::new(
+            self.simplex3(p),
+            self.simplex3(p + Vec3::new(31.4, 17.9, 5.3)),
+            self.simplex3(p + Vec3::new(-12.7, 48.1, 23.5)),
+        );
+        let derivative = |axis: Vec3| {
+            (potential(p + axis * CURL_EPSILON) - potential(p - axis * CURL_EPSILON)) / (2.0 * CURL_EPSILON)
+        };
+        let (dx, dy, dz) = (derivative(Vec3::X), derivative(Vec3::Y), derivative(Vec3::Z));
+        Vec3::new(dy.z - dz.y, dz.x - dx.z, dx.y - dy.x)
+    }
+
+    /// A table entry for integer coordinates, looking each coordinate up in turn.
+    fn hash(&self, coords: &[i32]) -> usize {
+        coords.iter().fold(0, |hash, coord| self.perm[hash + (coord & 255) as usize] as usize)
+    }
+
+    fn lattice_value(&self, coords: &[i32]) -> f32 {
+        self.hash(coords) as f32 / 127.5 - 1.0
+    }
+}
+
+/// Fractal Brownian motion: octaves of noise, each finer and fainter than the last.
+#[derive(Debug, Clone, Copy)]
+pub struct Fractal {
+    pub octaves: usize,
+    /// How much finer each octave is.
+    pub lacunarity: f32,
+    /// How much fainter each octave is.
+    pub gain: f32,
+}
+
+impl Default for Fractal {
+    fn default() -> Self {
+        Self {
+            octaves: 5,
+            lacunarity: 2.0,
+            gain: 0.5,
+        }
+    }
+}
+
+impl Fractal {
+    /// Sums octaves of `noise` at `p`, scaled back to the range of a single octave.
+    pub fn fbm<P>(&self, p: P, noise: impl Fn(P) -> f32) -> f32
+    where
+        P: Mul<f32, Output = P> + Add<f32, Output = P> + Copy,
+    {
+        let (mut total, mut amplitude, mut frequency, mut weight) = (0.0, 1.0, 1.0, 0.0);
+        for octave in 0..self.octaves.max(1) {
+            // Shifting each octave keeps their lattices from lining up at the origin.
+            total += noise(p * frequency + octave as f32 * 19.19) * amplitude;
+            weight += amplitude;
+            amplitude *= self.gain;
+            frequency *= self.lacunarity;
+        }
+        total / weight
+    }
+}
+
+/// Corner `corner` of a unit hypercube, one bit per axis starting with x.
+fn corner_offset(corner: usize) -> IVec4 {
+    IVec4::from_array(std::array::from_fn(|axis| ((corner >> axis) & 1) as i32))
+}
+
+/// Quintic smoothstep, so the blend has no kinks where cells meet.
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn fades<const N: usize>(local: &[f32; N]) -> [f32; N] {
+    local.map(fade)
+}
+
+/// Blends the corners of a cell down one axis at a time, x first, leaving the result in `corners[0]`.
+fn blend(corners: &mut [f32], fades: &[f32]) -> f32 {
+    let mut len = corners.len();
+    for t in fades {
+        len /= 2;
+        for idx in 0..len {
+            corners[idx] = corners[idx * 2] + (corners[idx * 2 + 1] - corners[idx * 2]) * t;
+        }
+    }
+    corners[0]
+}
+
+/// How many of the other coordinates each coordinate is larger than.
+fn ranks<const N: usize>(d: &[f32; N]) -> [i32; N] {
+    let mut rank = [0; N];
+    for a in 0..N {
+        for b in a + 1..N {
+            if d[a] > d[b] {
+                rank[a] += 1;
+            } else {
+                rank[b] += 1;
+            }
+        }
+    }
+    rank
+}
+
+/// A simplex corner's influence, fading to nothing at `radius_squared` away.
+fn falloff(radius_squared: f32, distance_squared: f32) -> f32 {
+    let t = (radius_squared - distance_squared).max(0.0);
+    t * t * t * t
+}
+
+fn grad2(hash: usize, d: Vec2) -> f32 {
+    GRAD2[hash & 7].dot(d)
+}
+
+/// One of the twelve edge midpoints of a cube, with four repeated to make sixteen.
+fn grad3(hash: usize, d: Vec3) -> f32 {
+    let h = hash & 15;
+    let u = if h < 8 { d.x } else { d.y };
+    let v = if h < 4 { d.y } else if h == 12 || h == 14 { d.x } else { d.z };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+/// One of the 32 edge midpoints of a tesseract.
+fn grad4(hash: usize, d: Vec4) -> f32 {
+    let h = hash & 31;
+    let u = if h < 24 { d.x } else { d.y };
+    let v = if h < 16 { d.y } else { d.z };
+    let w = if h < 8 { d.z } else { d.w };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v }) + (if h & 4 == 0 { w } else { -w })
+}
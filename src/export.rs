@@ -0,0 +1,516 @@
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use bevy::app::App;
+use bevy::asset::RenderAssetUsages;
+use bevy::prelude::*;
+use bevy::render::camera::SubCameraView;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat, TextureUsages};
+use bevy::render::view::screenshot::{Screenshot, ScreenshotCaptured};
+use bevy::render::view::RenderLayers;
+use bevy::tasks::{block_on, poll_once, IoTaskPool, Task};
+use bevy_egui::{egui, EguiContextPass, EguiContexts};
+use image::RgbaImage;
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+use crate::canvas::{CameraView, Canvas};
+use crate::common::Random;
+use crate::pdf::{frame_to_pdf, PdfInfo};
+use crate::recording::{Recording, RecordingSet};
+use crate::vision::VisionPreview;
+use crate::{ProgramState, UIState};
+
+/// Renders the active piece to an image file at a chosen print or screen size.
+pub struct ExportPlugin;
+
+impl Plugin for ExportPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<ExportSettings>()
+            .init_resource::<Export>()
+            .add_systems(EguiContextPass, params_ui.run_if(not(in_state(ProgramState::MainMenu))))
+            .add_systems(Update, run_export)
+            .add_systems(PostUpdate, run_pdf_export.after(RecordingSet))
+        ;
+    }
+}
+
+/// Largest texture side most GPUs will render to. Bigger exports are rendered in tiles.
+const MAX_TEXTURE_SIZE: u32 = 8192;
+/// Frames to wait after moving the export camera, so the render reflects it.
+const WARMUP_FRAMES: u32 = 2;
+const MM_PER_INCH: f32 = 25.4;
+/// The whole image is held in memory before saving, so this keeps it to about 2GB.
+const MAX_PIXELS: u64 = 500_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter)]
+pub enum Preset {
+    A0,
+    A1,
+    A2,
+    A3,
+    A4,
+    A5,
+    A6,
+    Square,
+    Uhd4k,
+    PhoneWallpaper,
+    Custom,
+}
+
+impl Preset {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Preset::A0 => "A0",
+            Preset::A1 => "A1",
+            Preset::A2 => "A2",
+            Preset::A3 => "A3",
+            Preset::A4 => "A4",
+            Preset::A5 => "A5",
+            Preset::A6 => "A6",
+            Preset::Square => "Square (1080 px)",
+            Preset::Uhd4k => "16:9 4K (3840 x 2160)",
+            Preset::PhoneWallpaper => "Phone wallpaper (1170 x 2532)",
+            Preset::Custom => "Custom",
+        }
+    }
+
+    /// Portrait paper size in millimeters, for presets that are physical sizes.
+    fn paper_mm(&self) -> Option<Vec2> {
+        match self {
+            Preset::A0 => Some(Vec2::new(841.0, 1189.0)),
+            Preset::A1 => Some(Vec2::new(594.0, 841.0)),
+            Preset::A2 => Some(Vec2::new(420.0, 594.0)),
+            Preset::A3 => Some(Vec2::new(297.0, 420.0)),
+            Preset::A4 => Some(Vec2::new(210.0, 297.0)),
+            Preset::A5 => Some(Vec2::new(148.0, 210.0)),
+            Preset::A6 => Some(Vec2::new(105.0, 148.0)),
+            _ => None,
+        }
+    }
+
+    /// Size in pixels, for presets meant for screens.
+    fn pixels(&self) -> Option<UVec2> {
+        match self {
+            Preset::Square => Some(UVec2::splat(1080)),
+            Preset::Uhd4k => Some(UVec2::new(3840, 2160)),
+            Preset::PhoneWallpaper => Some(UVec2::new(1170, 2532)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter)]
+pub enum Unit {
+    Millimeters,
+    Inches,
+}
+
+#[derive(Resource)]
+pub struct ExportSettings {
+    pub preset: Preset,
+    pub landscape: bool,
+    pub dpi: f32,
+    /// Size of the custom preset, in `unit`.
+    pub custom_size: Vec2,
+    pub unit: Unit,
+    pub directory: String,
+    /// Largest side of each tile the export is rendered in.
+    pub tile_size: u32,
+}
+
+impl Default for ExportSettings {
+    fn default() -> Self {
+        Self {
+            preset: Preset::Uhd4k,
+            landscape: true,
+            dpi: 300.0,
+            custom_size: Vec2::new(200.0, 200.0),
+            unit: Unit::Millimeters,
+            directory: "exports".into(),
+            tile_size: 4096,
+        }
+    }
+}
+
+impl ExportSettings {
+    /// Physical size of the output in millimeters, or `None` for screen presets.
+    pub fn physical_mm(&self) -> Option<Vec2> {
+        let size = match self.preset {
+            Preset::Custom => match self.unit {
+                Unit::Millimeters => self.custom_size,
+                Unit::Inches => self.custom_size * MM_PER_INCH,
+            },
+            preset => preset.paper_mm()?,
+        };
+        Some(self.orient(size))
+    }
+
+    /// Size of the output in pixels.
+    pub fn pixels(&self) -> UVec2 {
+        match self.physical_mm() {
+            Some(mm) => (mm / MM_PER_INCH * self.dpi).round().as_uvec2().max(UVec2::ONE),
+            None => self.preset.pixels().map_or(UVec2::ONE, |pixels| self.orient(pixels.as_vec2()).as_uvec2()),
+        }
+    }
+
+    pub fn aspect(&self) -> f32 {
+        let pixels = self.pixels().as_vec2();
+        pixels.x / pixels.y
+    }
+
+    /// Turns `size` so its long side runs across when `landscape` is set, and down otherwise.
+    fn orient(&self, size: Vec2) -> Vec2 {
+        let (long, short) = (size.max_element(), size.min_element());
+        if self.landscape { Vec2::new(long, short) } else { Vec2::new(short, long) }
+    }
+}
+
+/// The export currently in progress, if any.
+#[derive(Resource, Default)]
+pub struct Export {
+    job: Option<Job>,
+    /// Writing the finished image to disk, which can take a while for big prints.
+    saving: Option<Task<Result<PathBuf, String>>>,
+    /// Waiting on a recorded frame to write as a PDF.
+    pdf_pending: bool,
+    status: Option<String>,
+}
+
+impl Export {
+    /// Whether the export camera is rendering tiles.
+    pub fn is_rendering(&self) -> bool {
+        self.job.is_some()
+    }
+}
+
+/// Renders the image as a grid of tiles, each a slice of the full camera view, and stitches them together.
+struct Job {
+    path: PathBuf,
+    size: UVec2,
+    tile_size: UVec2,
+    tiles: UVec2,
+    next_tile: u32,
+    /// Render target for one tile.
+    image: Handle<Image>,
+    camera: Option<Entity>,
+    frames: u32,
+    requested: bool,
+    output: RgbaImage,
+}
+
+impl Job {
+    fn new(path: PathBuf, size: UVec2, max_tile: u32, images: &mut Assets<Image>) -> Self {
+        let tile_size = size.min(UVec2::splat(max_tile.clamp(1, MAX_TEXTURE_SIZE)));
+        Self {
+            path,
+            size,
+            tile_size,
+            tiles: tile_grid(size, tile_size),
+            next_tile: 0,
+            image: images.add(render_target(tile_size)),
+            camera: None,
+            frames: 0,
+            requested: false,
+            output: RgbaImage::new(size.x, size.y),
+        }
+    }
+
+    fn tile_count(&self) -> u32 {
+        self.tiles.x * self.tiles.y
+    }
+
+    /// Top left corner of a tile. The last row and column are pulled back inside the image,
+    /// overlapping their neighbours, so every tile can share one render target.
+    fn tile_offset(&self, tile: u32) -> UVec2 {
+        let cell = UVec2::new(tile % self.tiles.x, tile / self.tiles.x);
+        (cell * self.tile_size).min(self.size - self.tile_size)
+    }
+
+    fn sub_view(&self, tile: u32) -> SubCameraView {
+        SubCameraView {
+            full_size: self.size,
+            offset: self.tile_offset(tile).as_vec2(),
+            size: self.tile_size,
+        }
+    }
+
+    fn paste(&mut self, tile: &Image) -> Result<(), String> {
+        let tile_image = tile.clone().try_into_dynamic().map_err(|e| e.to_string())?.to_rgba8();
+        let offset = self.tile_offset(self.next_tile);
+        image::imageops::replace(&mut self.output, &tile_image, offset.x as i64, offset.y as i64);
+        Ok(())
+    }
+}
+
+/// How many tiles of `tile_size` it takes to cover `size`, across and down.
+fn tile_grid(size: UVec2, tile_size: UVec2) -> UVec2 {
+    (size + tile_size - UVec2::ONE) / tile_size
+}
+
+/// A camera rendering into `image`, framed on the canvas for an output of `size`.
+/// `sub_view` limits it to one tile of that output.
+fn export_camera(canvas: &Canvas, view: &CameraView, image: Handle<Image>, size: UVec2, sub_view: SubCameraView, clear_color: Color) -> impl Bundle {
+    // Interactive pan and zoom are for inspecting, so exports always frame the whole canvas.
+    let view = CameraView {
+        orthographic: view.orthographic,
+        ..default()
+    };
+    let mut projection = Projection::default();
+    let height = canvas.visible_height(size.x as f32 / size.y as f32);
+    let distance = view.camera_distance(&mut projection, height);
+
+    (
+        Camera3d::default(),
+        Camera {
+            target: image.into(),
+            order: -1,
+            sub_camera_view: Some(sub_view),
+            clear_color: ClearColorConfig::Custom(clear_color),
+            ..default()
+        },
+        projection,
+        Transform::from_xyz(0.0, 0.0, distance),
+        Msaa::Off,
+        RenderLayers::default(),
+    )
+}
+
+/// An image the GPU can render into and copy back out of.
+fn render_target(size: UVec2) -> Image {
+    let mut image = Image::new_uninit(
+        Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        TextureFormat::Bgra8UnormSrgb,
+        RenderAssetUsages::RENDER_WORLD,
+    );
+    image.texture_descriptor.usage = TextureUsages::TEXTURE_BINDING
+        | TextureUsages::COPY_DST
+        | TextureUsages::COPY_SRC
+        | TextureUsages::RENDER_ATTACHMENT;
+    image
+}
+
+/// A new file name in `directory` for the piece being exported.
+pub fn export_path(directory: &str, piece: &ProgramState, extension: &str) -> PathBuf {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+    let name = format!("{:?}", piece).to_lowercase();
+    Path::new(directory).join(format!("{}-{}.{}", name, seconds, extension))
+}
+
+fn run_export(
+    mut commands: Commands,
+    mut export: ResMut<Export>,
+    mut time: ResMut<Time<Virtual>>,
+    canvas: Res<Canvas>,
+    view: Res<CameraView>,
+    clear_color: Res<ClearColor>,
+    vision: Res<VisionPreview>,
+) {
+    if let Some(task) = export.saving.as_mut() {
+        if let Some(result) = block_on(poll_once(task)) {
+            export.status = Some(match result {
+                Ok(path) => format!("Saved {}", path.display()),
+                Err(e) => format!("Export failed: {}", e),
+            });
+            export.saving = None;
+        }
+    }
+
+    let Some(job) = export.job.as_mut() else {
+        return;
+    };
+
+    let Some(camera) = job.camera else {
+        // Freeze the animation so every tile shows the same moment.
+        time.pause();
+        let bundle = export_camera(&canvas, &view, job.image.clone(), job.size, job.sub_view(0), vision.for_export(clear_color.0));
+        job.camera = Some(commands.spawn(bundle).id());
+        return;
+    };
+
+    job.frames += 1;
+    if job.frames < WARMUP_FRAMES || job.requested {
+        return;
+    }
+    job.requested = true;
+
+    commands.spawn(Screenshot::image(job.image.clone())).observe(
+        move |trigger: Trigger<ScreenshotCaptured>,
+              mut commands: Commands,
+              mut export: ResMut<Export>,
+              mut time: ResMut<Time<Virtual>>,
+              mut cameras: Query<&mut Camera>| {
+            let Some(job) = export.job.as_mut() else {
+                return;
+            };
+
+            let pasted = job.paste(&trigger.event().0);
+            job.next_tile += 1;
+            if pasted.is_ok() && job.next_tile < job.tile_count() {
+                if let Ok(mut camera) = cameras.get_mut(camera) {
+                    camera.sub_camera_view = Some(job.sub_view(job.next_tile));
+                }
+                job.frames = 0;
+                job.requested = false;
+                return;
+            }
+
+            commands.entity(camera).despawn();
+            time.unpause();
+            let Some(job) = export.job.take() else {
+                return;
+            };
+            if let Err(e) = pasted {
+                export.status = Some(format!("Export failed: {}", e));
+                return;
+            }
+
+            export.status = Some(format!("Saving {}...", job.path.display()));
+            export.saving = Some(IoTaskPool::get().spawn(async move {
+                save_png(&job.output, &job.path)?;
+                Ok(job.path)
+            }));
+        },
+    );
+}
+
+fn save_png(image: &RgbaImage, path: &Path) -> Result<(), String> {
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory).map_err(|e| e.to_string())?;
+    }
+    image.save(path).map_err(|e| e.to_string())
+}
+
+/// Writes `contents` to `path`, creating its directory if needed.
+pub fn write_file(path: &Path, contents: impl AsRef<[u8]>) -> std::io::Result<()> {
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory)?;
+    }
+    std::fs::write(path, contents)
+}
+
+/// Writes the next recorded frame as a PDF, once one has been asked for.
+fn run_pdf_export(
+    mut export: ResMut<Export>,
+    mut recording: ResMut<Recording>,
+    settings: Res<ExportSettings>,
+    canvas: Res<Canvas>,
+    clear_color: Res<ClearColor>,
+    vision: Res<VisionPreview>,
+    program_state: Res<State<ProgramState>>,
+    rand: Res<Random>,
+) {
+    if !export.pdf_pending {
+        return;
+    }
+    let Some(frame) = recording.frame() else {
+        recording.request();
+        return;
+    };
+    export.pdf_pending = false;
+    let Some(paper) = settings.physical_mm() else {
+        return;
+    };
+
+    let info = PdfInfo {
+        title: &format!("{:?}", program_state.get()),
+        subject: &format!("seed: {}\n\n{}", rand.seed(), frame.params.join("\n\n")),
+    };
+    let pdf = frame_to_pdf(frame, &canvas, paper, settings.dpi, vision.for_export(clear_color.0), &info);
+    let path = export_path(&settings.directory, program_state.get(), "pdf");
+    export.status = Some(match write_file(&path, pdf) {
+        Ok(()) => format!("Saved {}", path.display()),
+        Err(e) => format!("Export failed: {}", e),
+    });
+}
+
+fn params_ui(
+    mut contexts: EguiContexts,
+    ui_state: Res<UIState>,
+    program_state: Res<State<ProgramState>>,
+    mut settings: ResMut<ExportSettings>,
+    mut export: ResMut<Export>,
+    mut canvas: ResMut<Canvas>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    if !ui_state.params_panel {
+        return;
+    }
+
+    egui::Window::new("Export").show(contexts.ctx_mut(), |ui| {
+        egui::ComboBox::from_label("Preset")
+            .selected_text(settings.preset.label())
+            .show_ui(ui, |ui| {
+                for preset in Preset::iter() {
+                    ui.selectable_value(&mut settings.preset, preset, preset.label());
+                }
+            });
+        ui.checkbox(&mut settings.landscape, "Landscape");
+
+        if settings.preset == Preset::Custom {
+            ui.horizontal(|ui| {
+                for unit in Unit::iter() {
+                    ui.radio_value(&mut settings.unit, unit, format!("{:?}", unit));
+                }
+            });
+            ui.add(egui::DragValue::new(&mut settings.custom_size.x).range(1.0..=10000.0).prefix("W "));
+            ui.add(egui::DragValue::new(&mut settings.custom_size.y).range(1.0..=10000.0).prefix("H "));
+        }
+        if settings.physical_mm().is_some() {
+            ui.add(egui::Slider::new(&mut settings.dpi, 72.0..=1200.0).text("DPI"));
+        }
+
+        let pixels = settings.pixels();
+        match settings.physical_mm() {
+            Some(mm) => ui.label(format!("{} x {} px, {:.0} x {:.0} mm", pixels.x, pixels.y, mm.x, mm.y)),
+            None => ui.label(format!("{} x {} px", pixels.x, pixels.y)),
+        };
+        if (settings.aspect() - canvas.aspect).abs() > 0.001 && ui.button("Match canvas aspect").clicked() {
+            canvas.aspect = settings.aspect();
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Directory");
+            ui.text_edit_singleline(&mut settings.directory);
+        });
+
+        ui.add(egui::Slider::new(&mut settings.tile_size, 256..=MAX_TEXTURE_SIZE).text("Tile Size"));
+        let tiles = tile_grid(pixels, UVec2::splat(settings.tile_size));
+        if tiles != UVec2::ONE {
+            ui.label(format!("Rendered in {} x {} tiles", tiles.x, tiles.y));
+        }
+
+        let too_big = u64::from(pixels.x) * u64::from(pixels.y) > MAX_PIXELS;
+        if too_big {
+            ui.colored_label(egui::Color32::RED, format!("Too large for PNG, which is limited to {} megapixels", MAX_PIXELS / 1_000_000));
+        }
+
+        let busy = export.job.is_some() || export.saving.is_some();
+        ui.horizontal(|ui| {
+            if ui.add_enabled(!busy && !too_big, egui::Button::new("Export PNG")).clicked() {
+                let path = export_path(&settings.directory, program_state.get(), "png");
+                export.job = Some(Job::new(path, pixels, settings.tile_size, &mut images));
+                export.status = None;
+            }
+            let printable = settings.physical_mm().is_some();
+            if ui.add_enabled(printable && !export.pdf_pending, egui::Button::new("Export PDF"))
+                .on_disabled_hover_text("PDFs need a paper size")
+                .clicked()
+            {
+                export.pdf_pending = true;
+                export.status = None;
+            }
+        });
+        if let Some(job) = &export.job {
+            let progress = job.next_tile as f32 / job.tile_count() as f32;
+            ui.add(egui::ProgressBar::new(progress).text(format!("Tile {} of {}", job.next_tile + 1, job.tile_count())));
+        }
+        if let Some(status) = &export.status {
+            ui.label(status);
+        }
+    });
+}
@@ -7,7 +7,10 @@ use std::{
 use bevy::{color::palettes::css::*, prelude::*};
 use bevy_vector_shapes::prelude::*;
 use crate::common::Modifier;
-use crate::ProgramState;
+use bevy_egui::{egui, EguiContextPass, EguiContexts};
+use crate::palette::Palettes;
+use crate::recording::Painter;
+use crate::{ProgramState, UIState};
 
 #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
 struct GallerySet;
@@ -21,6 +24,7 @@ impl Plugin for GalleryArt {
                                 GallerySet.run_if(in_state(ProgramState::Gallery)),
                             )
             )
+            .add_systems(EguiContextPass, params_ui)
             .add_systems(Update,
                          (
                              draw_gallery,
@@ -30,8 +34,13 @@ impl Plugin for GalleryArt {
     }
 }
 
-pub fn gallery(mut painter: ShapePainter, seconds: f32, entries: Range<i32>) {
+pub fn gallery(mut painter: Painter, palettes: &Palettes, seconds: f32, entries: Range<i32>) {
     let seconds = seconds % (2.0 * TAU);
+    // One colour per kind of shape. The palette's background is left out, since the gallery has none.
+    let colors: Vec<Srgba> = [MIDNIGHT_BLUE, SEA_GREEN, ORANGE, CRIMSON, PURPLE].iter()
+        .enumerate()
+        .map(|(idx, color)| palettes.sample(idx + 1, color.pastel()))
+        .collect();
     let start_pos = painter.transform;
 
     for i in entries {
@@ -44,21 +53,21 @@ pub fn gallery(mut painter: ShapePainter, seconds: f32, entries: Range<i32>) {
             // Line examples
             0 => {
                 painter.thickness = 0.5;
-                painter.set_color(MIDNIGHT_BLUE.pastel());
+                painter.set_color(colors[0]);
                 painter.cap = Cap::None;
                 painter.line(Vec3::new(-1.0, 1.0, 0.0), Vec3::new(1.0, 1.0, 0.0));
 
-                painter.set_color(MIDNIGHT_BLUE.pastel() * 1.2);
+                painter.set_color(colors[0].scale_lightness(1.2));
                 painter.cap = Cap::Square;
                 painter.line(Vec3::new(-1.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
 
-                painter.set_color(MIDNIGHT_BLUE.pastel() * 1.6);
+                painter.set_color(colors[0].scale_lightness(1.6));
                 painter.cap = Cap::Round;
                 painter.line(Vec3::new(-1.0, -1.0, 0.0), Vec3::new(1.0, -1.0, 0.0));
             }
             5 => {
                 painter.thickness = 0.5;
-                painter.set_color(MIDNIGHT_BLUE.pastel() * 1.4);
+                painter.set_color(colors[0].scale_lightness(1.4));
                 painter.cap = Cap::Round;
                 let rotation = Quat::from_rotation_z(seconds * 2.5);
                 let radius = ((seconds * 3.0).sin() + 0.5) / 2.0 * 1.5;
@@ -69,7 +78,7 @@ pub fn gallery(mut painter: ShapePainter, seconds: f32, entries: Range<i32>) {
             }
             10 => {
                 painter.thickness = 0.4;
-                painter.set_color(MIDNIGHT_BLUE.pastel());
+                painter.set_color(colors[0]);
                 painter.cap = Cap::Round;
                 painter.line((Vec3::X + Vec3::Y) * 0.2, (Vec3::NEG_X + Vec3::NEG_Y) * 0.2);
                 painter.line((Vec3::X + Vec3::NEG_Y) * 0.2, (Vec3::NEG_X + Vec3::Y) * 0.2);
@@ -95,17 +104,17 @@ pub fn gallery(mut painter: ShapePainter, seconds: f32, entries: Range<i32>) {
             }
             // Rect examples
             1 => {
-                painter.set_color(SEA_GREEN.pastel() * 0.7);
+                painter.set_color(colors[1].scale_lightness(0.7));
                 painter.corner_radii = Vec4::ZERO;
                 painter.translate(Vec3::Y);
                 painter.rect(Vec2::new(2.0, 0.7));
 
-                painter.set_color(SEA_GREEN.pastel());
+                painter.set_color(colors[1]);
                 painter.translate(-Vec3::Y);
                 painter.corner_radii = Vec4::splat(0.2);
                 painter.rect(Vec2::new(2.0, 0.7));
 
-                painter.set_color(SEA_GREEN.pastel() * 1.2);
+                painter.set_color(colors[1].scale_lightness(1.2));
                 painter.corner_radii = Vec4::new(0.0, 0.2, 0.35, 0.0);
                 painter.translate(-Vec3::Y);
                 painter.rect(Vec2::new(2.0, 0.7));
@@ -115,7 +124,7 @@ pub fn gallery(mut painter: ShapePainter, seconds: f32, entries: Range<i32>) {
                 let square_fill = ((seconds * 3.).sin() + 1.0) / 2.0;
 
                 painter.hollow = true;
-                painter.set_color(SEA_GREEN.pastel());
+                painter.set_color(colors[1]);
                 painter.thickness = 0.2 + 1.3 * square_fill;
                 painter.corner_radii = Vec4::splat(1.0);
                 painter.rotate_z(bar_fill * TAU);
@@ -132,7 +141,7 @@ pub fn gallery(mut painter: ShapePainter, seconds: f32, entries: Range<i32>) {
                 painter.corner_radii = Vec4::new(0.5, 0.5, 0.5, 0.8);
                 painter.rect(bar_size + bar_border * 3.0);
 
-                painter.set_color(SEA_GREEN.pastel() * (1.0 / (0.8 + bar_fill * 0.6)));
+                painter.set_color(colors[1].scale_lightness(1.0 / (0.8 + bar_fill * 0.6)));
                 painter.hollow = false;
                 painter.corner_radii -= 0.5 * Vec4::splat(bar_border * 3.0);
                 painter.translate(-Vec3::Y * (1.0 - bar_fill) * bar_size.y / 2.0);
@@ -141,13 +150,13 @@ pub fn gallery(mut painter: ShapePainter, seconds: f32, entries: Range<i32>) {
             // Circle examples
             2 => {
                 painter.hollow = false;
-                painter.set_color(ORANGE.pastel() * 0.7);
+                painter.set_color(colors[2].scale_lightness(0.7));
                 painter.translate(diag_vec * 0.8);
                 painter.circle(0.8);
 
                 painter.hollow = true;
                 painter.thickness = 0.4;
-                painter.set_color(ORANGE.pastel() * 1.4);
+                painter.set_color(colors[2].scale_lightness(1.4));
                 painter.translate(-diag_vec * 1.6);
                 painter.circle(0.8);
             }
@@ -155,13 +164,14 @@ pub fn gallery(mut painter: ShapePainter, seconds: f32, entries: Range<i32>) {
                 let circle_fill = ((seconds * 2.).sin() + 1.0) / 2.0;
 
                 painter.hollow = true;
-                painter.set_color(ORANGE.pastel());
+                painter.set_color(colors[2]);
                 painter.thickness = 0.5;
                 painter.circle(1.5 * circle_fill);
             }
             12 => {
                 fn draw_bubble(
-                    painter: &mut ShapePainter,
+                    painter: &mut Painter,
+                    color: Srgba,
                     seconds: f32,
                     position: Vec3,
                     scale: f32,
@@ -171,18 +181,19 @@ pub fn gallery(mut painter: ShapePainter, seconds: f32, entries: Range<i32>) {
 
                     painter.thickness = f32::powf(2.5, 2.8) / 40.0 * scale - circle_size;
                     painter.hollow = true;
-                    painter.set_color(ORANGE.pastel() + WHITE * circle_size);
+                    painter.set_color(color.lighten(circle_size));
                     painter.translate(position + Vec3::Y * circle_size * 2.0 * scale);
                     painter.circle(circle_size);
                 }
                 painter.translate(Vec3::NEG_Y * 0.6);
                 let start_pos = painter.transform;
-                draw_bubble(&mut painter, seconds, Vec3::X + Vec3::NEG_Y * 0.6, 1.1);
+                draw_bubble(&mut painter, colors[2], seconds, Vec3::X + Vec3::NEG_Y * 0.6, 1.1);
                 painter.transform = start_pos;
-                draw_bubble(&mut painter, seconds + 0.5, Vec3::NEG_Y, 1.7);
+                draw_bubble(&mut painter, colors[2], seconds + 0.5, Vec3::NEG_Y, 1.7);
                 painter.transform = start_pos;
                 draw_bubble(
                     &mut painter,
+                    colors[2],
                     seconds + PI / 3.0,
                     Vec3::NEG_X + Vec3::NEG_Y,
                     1.3,
@@ -190,6 +201,7 @@ pub fn gallery(mut painter: ShapePainter, seconds: f32, entries: Range<i32>) {
                 painter.transform = start_pos;
                 draw_bubble(
                     &mut painter,
+                    colors[2],
                     seconds + PI / 2.0,
                     Vec3::NEG_X * 0.5 + Vec3::NEG_Y * 1.2,
                     1.9,
@@ -197,6 +209,7 @@ pub fn gallery(mut painter: ShapePainter, seconds: f32, entries: Range<i32>) {
                 painter.transform = start_pos;
                 draw_bubble(
                     &mut painter,
+                    colors[2],
                     seconds + PI / 1.2,
                     Vec3::X * 0.7 + Vec3::NEG_Y * 1.4,
                     1.4,
@@ -206,14 +219,14 @@ pub fn gallery(mut painter: ShapePainter, seconds: f32, entries: Range<i32>) {
             3 => {
                 painter.hollow = false;
                 painter.cap = Cap::None;
-                painter.set_color(CRIMSON.pastel() * 0.7);
+                painter.set_color(colors[3].scale_lightness(0.7));
                 painter.translate(diag_vec * 0.8);
                 painter.arc(0.8, 0.0, TAU * (2. / 3.));
 
                 painter.hollow = true;
                 painter.cap = Cap::Round;
                 painter.thickness = 0.4;
-                painter.set_color(CRIMSON.pastel() * 1.4);
+                painter.set_color(colors[3].scale_lightness(1.4));
                 painter.translate(-diag_vec * 1.6);
                 painter.arc(0.8, 0.0, TAU * (2. / 3.));
             }
@@ -223,7 +236,7 @@ pub fn gallery(mut painter: ShapePainter, seconds: f32, entries: Range<i32>) {
 
                 painter.thickness = 0.5;
                 painter.hollow = true;
-                painter.set_color(CRIMSON.pastel());
+                painter.set_color(colors[3]);
                 painter.cap = Cap::None;
                 painter.arc(1.5, start_angle, end_angle);
             }
@@ -237,7 +250,7 @@ pub fn gallery(mut painter: ShapePainter, seconds: f32, entries: Range<i32>) {
                 painter.hollow = true;
                 painter.cap = Cap::Round;
                 painter.thickness = 0.4;
-                painter.set_color(CRIMSON.pastel() * (1.0 / (0.8 + meter_fill * 0.6)));
+                painter.set_color(colors[3].scale_lightness(1.0 / (0.8 + meter_fill * 0.6)));
                 painter.arc(1.3, start_angle, end_angle);
 
                 painter.cap = Cap::None;
@@ -258,31 +271,31 @@ pub fn gallery(mut painter: ShapePainter, seconds: f32, entries: Range<i32>) {
             4 => {
                 painter.thickness = 0.4;
                 painter.hollow = true;
-                painter.set_color(PURPLE.pastel() * 0.6);
+                painter.set_color(colors[4].scale_lightness(0.6));
                 painter.translate(diag_vec);
                 painter.ngon(4., 0.8);
 
                 painter.roundness = 0.1;
                 painter.hollow = false;
-                painter.set_color(PURPLE.pastel() * 0.8);
+                painter.set_color(colors[4].scale_lightness(0.8));
                 painter.translate(-Vec3::X * 2.0);
                 painter.ngon(3., 0.8);
 
                 painter.hollow = true;
-                painter.set_color(PURPLE.pastel());
+                painter.set_color(colors[4]);
                 painter.translate(-Vec3::Y * 2.0);
                 painter.ngon(5., 0.8);
 
                 painter.roundness = 0.0;
                 painter.hollow = false;
-                painter.set_color(PURPLE.pastel() * 1.2);
+                painter.set_color(colors[4].scale_lightness(1.2));
                 painter.translate(Vec3::X * 2.0);
                 painter.ngon(6., 0.8);
             }
             9 => {
                 painter.hollow = true;
                 painter.thickness = 0.5;
-                painter.set_color(PURPLE.pastel());
+                painter.set_color(colors[4]);
                 painter.roundness = 0.5;
                 painter.ngon(3. + (seconds.sin() + 1.) * 3., 1.5);
             }
@@ -293,18 +306,17 @@ pub fn gallery(mut painter: ShapePainter, seconds: f32, entries: Range<i32>) {
                 let down_vec = Quat::from_rotation_z(-PI * 7.0 / 6.0) * Vec3::Y * HEX_RADIUS * 2.0;
                 let right_vec = Quat::from_rotation_z(-PI * 5.0 / 6.0) * Vec3::Y * HEX_RADIUS * 2.0;
 
-                fn draw_gon(painter: &mut ShapePainter, origin: Vec3, sides: f32, radius: f32) {
+                fn draw_gon(painter: &mut Painter, origin: Vec3, sides: f32, radius: f32) {
                     let dist = ((painter.transform.translation - origin) / painter.transform.scale)
                         .length();
                     if dist <= BOUNDS {
                         let ratio = 1.0 - f32::max(dist, 0.5) / BOUNDS;
-                        painter.set_color(PURPLE.pastel());
                         painter.color.set_alpha(ratio);
                         painter.ngon(sides, radius * f32::powf(ratio, 0.2) * 0.8);
                     }
                 }
 
-                fn draw_position(painter: &mut ShapePainter, pos: Vec3, right: Vec3) {
+                fn draw_position(painter: &mut Painter, pos: Vec3, right: Vec3) {
                     let origin_tf = painter.transform;
 
                     painter.translate(pos);
@@ -325,6 +337,7 @@ pub fn gallery(mut painter: ShapePainter, seconds: f32, entries: Range<i32>) {
 
                 painter.hollow = false;
                 painter.roundness = 0.0;
+                painter.set_color(colors[4]);
 
                 let offset = down_vec * (seconds % PI) / PI
                     + right_vec * (seconds % (PI / 3.0)) / (PI / 3.0);
@@ -343,6 +356,24 @@ pub fn gallery(mut painter: ShapePainter, seconds: f32, entries: Range<i32>) {
     }
 }
 
-fn draw_gallery(time: Res<Time>, painter: ShapePainter) {
-    gallery(painter, time.elapsed_secs(), 0..15);
+fn draw_gallery(time: Res<Time>, painter: Painter, palettes: Res<Palettes>) {
+    gallery(painter, &palettes, time.elapsed_secs(), 0..15);
+}
+
+fn params_ui(
+    mut contexts: EguiContexts,
+    ui_state: Res<UIState>,
+    program_state: Res<State<ProgramState>>,
+    mut palettes: ResMut<Palettes>,
+) {
+    if !ui_state.params_panel || !program_state.eq(&ProgramState::Gallery) {
+        return;
+    }
+
+    egui::Window::new("Params").show(contexts.ctx_mut(), |ui| {
+        ui.heading("Palette");
+        if palettes.bypass_change_detection().ui(ui) {
+            palettes.set_changed();
+        }
+    });
 }
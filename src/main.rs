@@ -1,8 +1,18 @@
 mod circle;
 mod gallery;
 mod bubbles;
+mod canvas;
 mod common;
+mod export;
+mod mask;
+mod palette;
+mod palette_gen;
+mod pdf;
+mod plot;
+mod recording;
+mod strokes;
 mod summer_leaves;
+mod vision;
 
 use bevy::color::palettes::css::DIM_GRAY;
 use bevy::prelude::*;
@@ -14,10 +24,16 @@ use bevy_vector_shapes::prelude::*;
 use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
 use crate::bubbles::BubbleArt;
+use crate::canvas::{CanvasCamera, CanvasPlugin};
 use crate::circle::CircleArt;
-use crate::common::CachedRandom;
+use crate::common::Random;
+use crate::export::ExportPlugin;
 use crate::gallery::GalleryArt;
+use crate::palette::PalettePlugin;
+use crate::plot::PlotPlugin;
+use crate::recording::RecordingPlugin;
 use crate::summer_leaves::LeafArt;
+use crate::vision::VisionPlugin;
 
 fn main() {
     App::new()
@@ -36,7 +52,7 @@ fn main() {
         .add_plugins(EguiPlugin { enable_multipass_for_primary_context: true })
         .add_event::<Quit>()
         .init_resource::<UIState>()
-        .init_resource::<CachedRandom>()
+        .init_resource::<Random>()
         .init_state::<ProgramState>()
         .add_systems(EguiContextPass, ProgramState::selection_system.run_if(in_state(ProgramState::MainMenu)))
         .add_systems(Startup, setup)
@@ -44,6 +60,7 @@ fn main() {
             ProgramState::shortcuts,
             exit_system,
             ))
+        .add_plugins((CanvasPlugin, RecordingPlugin, ExportPlugin, PlotPlugin, PalettePlugin, VisionPlugin))
         .add_plugins((
             CircleArt,
             GalleryArt,
@@ -139,6 +156,7 @@ fn setup(mut commands: Commands) {
         Transform::from_xyz(0., 0., 16.).looking_at(Vec3::ZERO, Vec3::Y),
         Msaa::Off,
         RenderLayers::default(),
+        CanvasCamera,
     ));
 }
 
@@ -0,0 +1,105 @@
+use std::fmt;
+use std::path::Path;
+use bevy::prelude::*;
+use bevy_egui::egui;
+
+/// A grayscale bitmap used to weight where pieces place things.
+pub struct Mask {
+    width: u32,
+    height: u32,
+    values: Vec<f32>,
+}
+
+impl Mask {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, image::ImageError> {
+        let image = image::open(path)?.into_luma8();
+        let (width, height) = image.dimensions();
+        let values = image.pixels().map(|pixel| pixel.0[0] as f32 / 255.0).collect();
+
+        Ok(Self {
+            width,
+            height,
+            values,
+        })
+    }
+
+    /// Brightness at `uv`, where (0, 0) is the bottom left of the image and (1, 1) the top right.
+    pub fn sample(&self, uv: Vec2) -> f32 {
+        if self.values.is_empty() {
+            return 0.0;
+        }
+        let x = (uv.x.clamp(0.0, 1.0) * (self.width - 1) as f32).round() as u32;
+        let y = ((1.0 - uv.y.clamp(0.0, 1.0)) * (self.height - 1) as f32).round() as u32;
+        self.values[(y * self.width + x) as usize]
+    }
+}
+
+/// Just the size, since the values can run to millions.
+impl fmt::Debug for Mask {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mask")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A mask along with the path it was loaded from, for use in a params panel.
+#[derive(Default, Debug)]
+pub struct MaskSource {
+    pub path: String,
+    pub invert: bool,
+    mask: Option<Mask>,
+    error: Option<String>,
+}
+
+impl MaskSource {
+    pub fn is_loaded(&self) -> bool {
+        self.mask.is_some()
+    }
+
+    /// Brightness at `uv`, or `None` if no mask is loaded.
+    pub fn sample(&self, uv: Vec2) -> Option<f32> {
+        let value = self.mask.as_ref()?.sample(uv);
+        Some(if self.invert { 1.0 - value } else { value })
+    }
+
+    /// Brightness at `pos`, with the image stretched over a rectangle centered on the origin.
+    pub fn sample_bounds(&self, pos: Vec2, bounds: Vec2) -> Option<f32> {
+        self.sample((pos / bounds + 1.0) / 2.0)
+    }
+
+    /// Draws the mask controls. Returns true if the mask changed.
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = false;
+
+        ui.horizontal(|ui| {
+            ui.label("Mask");
+            ui.text_edit_singleline(&mut self.path);
+            if ui.button("Load").clicked() {
+                match Mask::load(&self.path) {
+                    Ok(mask) => {
+                        self.mask = Some(mask);
+                        self.error = None;
+                    }
+                    Err(e) => {
+                        self.mask = None;
+                        self.error = Some(e.to_string());
+                    }
+                }
+                changed = true;
+            }
+            if self.mask.is_some() && ui.button("Clear").clicked() {
+                self.mask = None;
+                changed = true;
+            }
+        });
+        changed |= ui.checkbox(&mut self.invert, "Invert mask").changed();
+
+        if let Some(error) = &self.error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+
+        changed
+    }
+}
@@ -0,0 +1,439 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use bevy::prelude::*;
+use bevy_egui::egui;
+use bevy::color::ColorToPacked;
+use crate::export::write_file;
+use crate::palette_gen::PaletteGenerator;
+
+/// Palettes in here are added to the library at startup, and saved palettes are written here.
+const PALETTE_DIRECTORY: &str = "palettes";
+
+/// Named palettes shared by every piece.
+pub struct PalettePlugin;
+
+impl Plugin for PalettePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<Palettes>()
+            .add_systems(Startup, load_library)
+        ;
+    }
+}
+
+/// What a piece uses a palette colour for. Palettes list their colours in this order,
+/// and pieces wrap around shorter palettes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Background,
+    Primary,
+    Secondary,
+    Accent,
+}
+
+#[derive(Debug, Clone)]
+pub struct Palette {
+    pub name: String,
+    /// Never empty.
+    pub colors: Vec<Srgba>,
+}
+
+impl Palette {
+    pub fn role(&self, role: Role) -> Srgba {
+        self.color(role as usize)
+    }
+
+    /// The `idx`th colour, wrapping around.
+    pub fn color(&self, idx: usize) -> Srgba {
+        self.colors[idx % self.colors.len()]
+    }
+
+    /// Reads a GIMP `.gpl`, an Adobe `.ase`, or anything else as a list of hex codes.
+    /// The palette is named after the file unless the file names itself.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let name = path.file_stem().map_or("Untitled".into(), |stem| stem.to_string_lossy().into_owned());
+        let extension = path.extension().map(|ext| ext.to_string_lossy().to_lowercase());
+        let palette = match extension.as_deref() {
+            Some("ase") => parse_ase(name, &fs::read(path).map_err(|e| e.to_string())?)?,
+            Some("gpl") => parse_gpl(name, &fs::read_to_string(path).map_err(|e| e.to_string())?)?,
+            _ => parse_hex(name, &fs::read_to_string(path).map_err(|e| e.to_string())?)?,
+        };
+        if palette.colors.is_empty() {
+            return Err(format!("No colours in {}", path.display()));
+        }
+        Ok(palette)
+    }
+
+    /// Writes the palette into `directory` as a GIMP `.gpl`, named after the palette.
+    /// Doesn't overwrite an existing file, numbering the new one instead.
+    pub fn save(&self, directory: impl AsRef<Path>) -> Result<PathBuf, String> {
+        let stem: String = self.name.chars()
+            .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+        let mut path = directory.as_ref().join(format!("{}.gpl", stem));
+        for idx in 2.. {
+            if !path.exists() {
+                break;
+            }
+            path = directory.as_ref().join(format!("{}-{}.gpl", stem, idx));
+        }
+        write_file(&path, self.to_gpl()).map_err(|e| e.to_string())?;
+        Ok(path)
+    }
+
+    fn to_gpl(&self) -> String {
+        let mut out = format!("GIMP Palette\nName: {}\n#\n", self.name);
+        for color in &self.colors {
+            let [r, g, b] = color.to_u8_array_no_alpha();
+            out += &format!("{:3} {:3} {:3}\t{}\n", r, g, b, color.to_hex());
+        }
+        out
+    }
+
+    fn from_hex(name: &str, codes: &[&str]) -> Self {
+        Self {
+            name: name.into(),
+            colors: codes.iter().filter_map(|code| Srgba::hex(code).ok()).collect(),
+        }
+    }
+}
+
+/// Hex codes separated by whitespace or commas. Lines starting with `;` or `//` are comments.
+fn parse_hex(name: String, text: &str) -> Result<Palette, String> {
+    let mut colors = Vec::new();
+    for line in text.lines().map(str::trim) {
+        if line.starts_with(';') || line.starts_with("//") {
+            continue;
+        }
+        for code in line.split(|c: char| c.is_whitespace() || c == ',').filter(|code| !code.is_empty()) {
+            colors.push(Srgba::hex(code).map_err(|_| format!("Not a hex colour: {}", code))?);
+        }
+    }
+    Ok(Palette { name, colors })
+}
+
+/// GIMP palettes: a header, then one `red green blue name` line per colour.
+fn parse_gpl(mut name: String, text: &str) -> Result<Palette, String> {
+    let mut lines = text.lines().map(str::trim);
+    if lines.next() != Some("GIMP Palette") {
+        return Err("Not a GIMP palette".into());
+    }
+
+    let mut colors = Vec::new();
+    for line in lines {
+        if let Some(named) = line.strip_prefix("Name:") {
+            name = named.trim().into();
+            continue;
+        }
+        if line.is_empty() || line.starts_with('#') || line.starts_with("Columns:") {
+            continue;
+        }
+        let channels: Vec<u8> = line.split_whitespace()
+            .take(3)
+            .map(|channel| channel.parse().map_err(|_| format!("Bad colour line: {}", line)))
+            .collect::<Result<_, _>>()?;
+        let [r, g, b] = channels[..] else {
+            return Err(format!("Bad colour line: {}", line));
+        };
+        colors.push(Srgba::rgb_u8(r, g, b));
+    }
+    Ok(Palette { name, colors })
+}
+
+/// Adobe Swatch Exchange: big-endian blocks, of which only colour entries matter here.
+/// RGB, CMYK and gray swatches are read, Lab ones are skipped.
+fn parse_ase(name: String, bytes: &[u8]) -> Result<Palette, String> {
+    const COLOR_ENTRY: u16 = 0x0001;
+
+    let mut reader = Reader { bytes };
+    if reader.take(4)? != b"ASEF" {
+        return Err("Not an ASE file".into());
+    }
+    // Version.
+    reader.take(4)?;
+
+    let mut colors = Vec::new();
+    for _ in 0..reader.u32()? {
+        let kind = reader.u16()?;
+        let length = reader.u32()? as usize;
+        let mut block = Reader { bytes: reader.take(length)? };
+        if kind != COLOR_ENTRY {
+            continue;
+        }
+
+        // The swatch name, as UTF-16 code units.
+        let name_length = block.u16()? as usize;
+        block.take(name_length * 2)?;
+        let color = match block.take(4)? {
+            b"RGB " => Srgba::rgb(block.f32()?, block.f32()?, block.f32()?),
+            b"Gray" => {
+                let gray = block.f32()?;
+                Srgba::rgb(gray, gray, gray)
+            }
+            b"CMYK" => {
+                let (c, m, y, k) = (block.f32()?, block.f32()?, block.f32()?, block.f32()?);
+                Srgba::rgb((1.0 - c) * (1.0 - k), (1.0 - m) * (1.0 - k), (1.0 - y) * (1.0 - k))
+            }
+            _ => continue,
+        };
+        colors.push(color);
+    }
+    Ok(Palette { name, colors })
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        if count > self.bytes.len() {
+            return Err("File ends early".into());
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+
+    fn f32(&mut self) -> Result<f32, String> {
+        Ok(f32::from_be_bytes(self.array()?))
+    }
+}
+
+/// The palette library, and which palette the pieces are drawn with.
+#[derive(Resource)]
+pub struct Palettes {
+    pub library: Vec<Palette>,
+    /// `None` leaves each piece with its own colours.
+    pub active: Option<usize>,
+    pub path: String,
+    pub generator: PaletteGenerator,
+    error: Option<String>,
+}
+
+impl Default for Palettes {
+    fn default() -> Self {
+        Self {
+            library: vec![
+                Palette::from_hex("Ocean", &["0b2545", "8da9c4", "134074", "eef4ed"]),
+                Palette::from_hex("Forest", &["2f3e2c", "7fa36b", "4e6e3a", "f2e6a0"]),
+                Palette::from_hex("Sunset", &["2d1e2f", "f28f3b", "c8553d", "ffd5c2", "588b8b"]),
+                Palette::from_hex("Berry", &["2b0f2e", "d1495b", "8f2d56", "edae49", "66a182"]),
+                Palette::from_hex("Mono", &["1a1a1a", "e0e0e0", "8c8c8c", "ffffff"]),
+            ],
+            active: None,
+            path: String::new(),
+            generator: PaletteGenerator::default(),
+            error: None,
+        }
+    }
+}
+
+impl Palettes {
+    pub fn active(&self) -> Option<&Palette> {
+        self.library.get(self.active?)
+    }
+
+    /// The active palette's colour for `role`, or `fallback` if no palette is active.
+    pub fn pick(&self, role: Role, fallback: Srgba) -> Srgba {
+        self.active().map_or(fallback, |palette| palette.role(role))
+    }
+
+    /// The active palette's `idx`th colour, or `fallback` if no palette is active.
+    pub fn sample(&self, idx: usize, fallback: Srgba) -> Srgba {
+        self.active().map_or(fallback, |palette| palette.color(idx))
+    }
+
+    /// Adds `palette` to the library and makes it the active one.
+    pub fn add(&mut self, palette: Palette) {
+        self.library.push(palette);
+        self.active = Some(self.library.len() - 1);
+    }
+
+    /// Draws the palette picker, loader and generator. Returns true if the active palette changed.
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let before = self.active;
+
+        let selected = self.active().map_or("Piece colours", |palette| palette.name.as_str()).to_owned();
+        egui::ComboBox::from_label("Palette")
+            .selected_text(selected)
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.active, None, "Piece colours");
+                for (idx, palette) in self.library.iter().enumerate() {
+                    ui.selectable_value(&mut self.active, Some(idx), &palette.name);
+                }
+            });
+
+        if let Some(palette) = self.active() {
+            swatches(ui, &palette.colors);
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("File");
+            ui.text_edit_singleline(&mut self.path);
+            if ui.button("Load").clicked() {
+                match Palette::load(&self.path) {
+                    Ok(palette) => {
+                        self.add(palette);
+                        self.error = None;
+                    }
+                    Err(e) => self.error = Some(e),
+                }
+            }
+        });
+
+        if let Some(error) = &self.error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+
+        egui::CollapsingHeader::new("Generate").show(ui, |ui| {
+            if let Some(palette) = self.generator.ui(ui) {
+                match palette.save(PALETTE_DIRECTORY) {
+                    Ok(_) => {
+                        self.add(palette);
+                        self.error = None;
+                    }
+                    Err(e) => self.error = Some(e),
+                }
+            }
+        });
+
+        self.active != before
+    }
+}
+
+/// Adds every palette in `PALETTE_DIRECTORY` to the library, in file name order.
+fn load_library(mut palettes: ResMut<Palettes>) {
+    let Ok(entries) = fs::read_dir(PALETTE_DIRECTORY) else {
+        return;
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file())
+        .collect();
+    paths.sort();
+
+    for path in paths {
+        match Palette::load(&path) {
+            Ok(palette) => palettes.library.push(palette),
+            Err(e) => palettes.error = Some(format!("{}: {}", path.display(), e)),
+        }
+    }
+}
+
+/// A row of little squares, one per colour.
+pub fn swatches(ui: &mut egui::Ui, colors: &[Srgba]) {
+    ui.horizontal_wrapped(|ui| {
+        ui.spacing_mut().item_spacing.x = 2.0;
+        for color in colors {
+            let [r, g, b] = color.to_u8_array_no_alpha();
+            let (rect, _) = ui.allocate_exact_size(egui::vec2(16.0, 16.0), egui::Sense::hover());
+            ui.painter().rect_filled(rect, 2.0, egui::Color32::from_rgb(r, g, b));
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An ASE colour entry block, with a one-letter swatch name.
+    fn ase_block(model: &[u8; 4], channels: &[f32]) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&2u16.to_be_bytes());
+        body.extend_from_slice(&[0, b'a', 0, 0]);
+        body.extend_from_slice(model);
+        for channel in channels {
+            body.extend_from_slice(&channel.to_be_bytes());
+        }
+        // Global, spot or process colour, which isn't read.
+        body.extend_from_slice(&0u16.to_be_bytes());
+
+        let mut block = Vec::new();
+        block.extend_from_slice(&1u16.to_be_bytes());
+        block.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        block.extend_from_slice(&body);
+        block
+    }
+
+    fn ase(blocks: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = b"ASEF".to_vec();
+        bytes.extend_from_slice(&[0, 1, 0, 0]);
+        bytes.extend_from_slice(&(blocks.len() as u32).to_be_bytes());
+        for block in blocks {
+            bytes.extend_from_slice(block);
+        }
+        bytes
+    }
+
+    #[test]
+    fn ase_reads_rgb_and_gray() {
+        let bytes = ase(&[ase_block(b"RGB ", &[1.0, 0.5, 0.0]), ase_block(b"Gray", &[0.25])]);
+        let palette = parse_ase("test".into(), &bytes).unwrap();
+        assert_eq!(palette.colors, vec![Srgba::rgb(1.0, 0.5, 0.0), Srgba::rgb(0.25, 0.25, 0.25)]);
+    }
+
+    #[test]
+    fn ase_skips_lab() {
+        let bytes = ase(&[ase_block(b"LAB ", &[50.0, 0.0, 0.0]), ase_block(b"RGB ", &[0.0, 0.0, 1.0])]);
+        assert_eq!(parse_ase("test".into(), &bytes).unwrap().colors, vec![Srgba::rgb(0.0, 0.0, 1.0)]);
+    }
+
+    #[test]
+    fn ase_truncated_block_is_an_error() {
+        let mut bytes = ase(&[ase_block(b"RGB ", &[1.0, 0.5, 0.0])]);
+        bytes.truncate(bytes.len() - 6);
+        assert!(parse_ase("test".into(), &bytes).is_err());
+    }
+
+    #[test]
+    fn ase_bad_header_is_an_error() {
+        assert!(parse_ase("test".into(), b"ASEX\0\x01\0\0\0\0\0\0").is_err());
+        assert!(parse_ase("test".into(), b"AS").is_err());
+    }
+
+    #[test]
+    fn gpl_reads_colours_and_name() {
+        let text = "GIMP Palette\nName: Sunset\nColumns: 4\n# comment\n255 128   0\tOrange\n  0   0 255 Blue\n";
+        let palette = parse_gpl("file".into(), text).unwrap();
+        assert_eq!(palette.name, "Sunset");
+        assert_eq!(palette.colors, vec![Srgba::rgb_u8(255, 128, 0), Srgba::rgb_u8(0, 0, 255)]);
+    }
+
+    #[test]
+    fn gpl_bad_lines_are_errors() {
+        assert!(parse_gpl("file".into(), "Not a palette\n255 0 0\n").is_err());
+        assert!(parse_gpl("file".into(), "GIMP Palette\n255 0\n").is_err());
+        assert!(parse_gpl("file".into(), "GIMP Palette\n300 0 0\n").is_err());
+    }
+
+    #[test]
+    fn gpl_round_trips() {
+        let palette = Palette::from_hex("Saved", &["102030", "ffeedd"]);
+        let loaded = parse_gpl("file".into(), &palette.to_gpl()).unwrap();
+        assert_eq!(loaded.name, "Saved");
+        assert_eq!(loaded.colors, palette.colors);
+    }
+
+    #[test]
+    fn hex_splits_on_commas_and_whitespace() {
+        let palette = parse_hex("file".into(), "; comment\n#ff0000, 00ff00\n#0000ff").unwrap();
+        assert_eq!(palette.colors.len(), 3);
+        assert!(parse_hex("file".into(), "#ff0000 nope").is_err());
+    }
+}
@@ -0,0 +1,276 @@
+use std::f32::consts::TAU;
+use bevy::color::{Oklaba, Oklcha};
+use bevy::prelude::*;
+use bevy_egui::egui;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+use crate::common::{gamut_map, seed_edit};
+use crate::palette::{swatches, Palette};
+
+/// Pixels k-means looks at, spread evenly over the image.
+const MAX_SAMPLES: usize = 20_000;
+const KMEANS_ITERATIONS: usize = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter)]
+pub enum Generator {
+    Complementary,
+    Triadic,
+    Analogous,
+    Cosine,
+    Image,
+}
+
+impl Generator {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Generator::Complementary => "Complementary",
+            Generator::Triadic => "Triadic",
+            Generator::Analogous => "Analogous",
+            Generator::Cosine => "Cosine Gradient",
+            Generator::Image => "From Image",
+        }
+    }
+
+    /// Hue offsets from the base hue, in degrees, for the harmonies.
+    fn offsets(&self) -> &'static [f32] {
+        match self {
+            Generator::Complementary => &[0.0, 180.0],
+            Generator::Triadic => &[0.0, 120.0, 240.0],
+            Generator::Analogous => &[0.0, 30.0, -30.0],
+            Generator::Cosine | Generator::Image => &[],
+        }
+    }
+}
+
+/// Settings for making new palettes. The same settings and seed always make the same palette.
+#[derive(Debug)]
+pub struct PaletteGenerator {
+    pub generator: Generator,
+    pub seed: u64,
+    /// Degrees, in Oklch.
+    pub base_hue: f32,
+    pub count: usize,
+    pub image_path: String,
+    /// The image's pixels in Oklab, read when Extract is clicked so changing the seed or count only reruns k-means.
+    samples: Option<Vec<Vec3>>,
+    preview: Option<Palette>,
+    error: Option<String>,
+}
+
+impl Default for PaletteGenerator {
+    fn default() -> Self {
+        Self {
+            generator: Generator::Triadic,
+            seed: 1,
+            base_hue: 220.0,
+            count: 5,
+            image_path: String::new(),
+            samples: None,
+            preview: None,
+            error: None,
+        }
+    }
+}
+
+impl PaletteGenerator {
+    pub fn generate(&self) -> Result<Palette, String> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let count = self.count.max(1);
+        let colors = match self.generator {
+            Generator::Complementary | Generator::Triadic | Generator::Analogous => {
+                harmony(self.base_hue, self.generator.offsets(), count, &mut rng)
+            }
+            Generator::Cosine => darkest_first(cosine(count, &mut rng)),
+            Generator::Image => {
+                let samples = self.samples.as_deref().ok_or("No image has been extracted")?;
+                darkest_first(kmeans(samples, count, &mut rng))
+            }
+        };
+        Ok(Palette {
+            name: format!("{} {}", self.generator.label(), self.seed),
+            colors,
+        })
+    }
+
+    /// Draws the generator controls and a preview. Returns the previewed palette when it's saved.
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> Option<Palette> {
+        let mut changed = false;
+
+        let before = self.generator;
+        egui::ComboBox::from_label("Generator")
+            .selected_text(self.generator.label())
+            .show_ui(ui, |ui| {
+                for generator in Generator::iter() {
+                    ui.selectable_value(&mut self.generator, generator, generator.label());
+                }
+            });
+        if self.generator != before {
+            // The last generator's palette mustn't be left up to be saved as this one's.
+            self.preview = None;
+            self.error = None;
+            changed = true;
+        }
+        changed |= seed_edit(ui, &mut self.seed);
+        changed |= ui.add(egui::Slider::new(&mut self.count, 2..=12).text("Colours")).changed();
+        match self.generator {
+            Generator::Complementary | Generator::Triadic | Generator::Analogous => {
+                changed |= ui.add(egui::Slider::new(&mut self.base_hue, 0.0..=360.0).text("Base Hue")).changed();
+            }
+            Generator::Cosine => {}
+            Generator::Image => {
+                ui.horizontal(|ui| {
+                    ui.label("Image");
+                    ui.text_edit_singleline(&mut self.image_path);
+                    if ui.button("Extract").clicked() {
+                        match sample_image(&self.image_path) {
+                            Ok(samples) => self.samples = Some(samples),
+                            Err(e) => {
+                                self.samples = None;
+                                self.preview = None;
+                                self.error = Some(e);
+                            }
+                        }
+                        changed = true;
+                    }
+                });
+            }
+        }
+
+        // Images are only clustered once one has been read.
+        let ready = self.generator != Generator::Image || self.samples.is_some();
+        if ready && (changed || (self.preview.is_none() && self.error.is_none())) {
+            match self.generate() {
+                Ok(palette) => {
+                    self.preview = Some(palette);
+                    self.error = None;
+                }
+                Err(e) => {
+                    self.preview = None;
+                    self.error = Some(e);
+                }
+            }
+        }
+
+        if let Some(error) = &self.error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+        let preview = self.preview.as_ref()?;
+        swatches(ui, &preview.colors);
+        ui.button("Save to Library").clicked().then(|| preview.clone())
+    }
+}
+
+/// A dark, greyed background in the base hue, then colours around the harmony's hues.
+fn harmony(base_hue: f32, offsets: &[f32], count: usize, rng: &mut StdRng) -> Vec<Srgba> {
+    let mut colors = vec![gamut_map(Oklcha::new(rng.random_range(0.18..0.3), rng.random_range(0.02..0.06), base_hue, 1.0))];
+    for i in 0..count - 1 {
+        let hue = base_hue + offsets[i % offsets.len()] + rng.random_range(-8.0..8.0);
+        let lightness = rng.random_range(0.55..0.85);
+        let chroma = rng.random_range(0.08..0.16);
+        colors.push(gamut_map(Oklcha::new(lightness, chroma, hue.rem_euclid(360.0), 1.0)));
+    }
+    colors
+}
+
+/// Samples `a + b * cos(2π(c * t + d))` per channel, with the coefficients picked at random.
+fn cosine(count: usize, rng: &mut StdRng) -> Vec<Srgba> {
+    let a: [f32; 3] = std::array::from_fn(|_| rng.random_range(0.4..0.6));
+    let b: [f32; 3] = std::array::from_fn(|_| rng.random_range(0.25..0.5));
+    let c: [f32; 3] = std::array::from_fn(|_| rng.random_range(0.5..1.5));
+    let d: [f32; 3] = std::array::from_fn(|_| rng.random_range(0.0..1.0));
+
+    (0..count)
+        .map(|i| {
+            let t = i as f32 / (count - 1).max(1) as f32;
+            let [red, green, blue] = std::array::from_fn(|ch| (a[ch] + b[ch] * (TAU * (c[ch] * t + d[ch])).cos()).clamp(0.0, 1.0));
+            Srgba::rgb(red, green, blue)
+        })
+        .collect()
+}
+
+/// Up to `MAX_SAMPLES` of the image's pixels, spread evenly over it, in Oklab.
+fn sample_image(path: &str) -> Result<Vec<Vec3>, String> {
+    let image = image::open(path).map_err(|e| e.to_string())?.into_rgb8();
+    let stride = (image.width() as usize * image.height() as usize / MAX_SAMPLES).max(1);
+    let samples: Vec<Vec3> = image.pixels()
+        .step_by(stride)
+        .map(|pixel| {
+            let [r, g, b] = pixel.0;
+            let lab = Oklaba::from(Srgba::rgb_u8(r, g, b));
+            Vec3::new(lab.lightness, lab.a, lab.b)
+        })
+        .collect();
+    if samples.is_empty() {
+        return Err("The image is empty".into());
+    }
+    Ok(samples)
+}
+
+/// The most common colours among `samples`, found with k-means in Oklab.
+fn kmeans(samples: &[Vec3], count: usize, rng: &mut StdRng) -> Vec<Srgba> {
+    let mut centers = seed_centers(samples, count.min(samples.len()), rng);
+    for _ in 0..KMEANS_ITERATIONS {
+        let mut sums = vec![(Vec3::ZERO, 0); centers.len()];
+        for sample in samples {
+            let (sum, members) = &mut sums[nearest(&centers, *sample)];
+            *sum += *sample;
+            *members += 1;
+        }
+
+        let mut moved: f32 = 0.0;
+        for (center, (sum, members)) in centers.iter_mut().zip(sums) {
+            if members > 0 {
+                let mean = sum / members as f32;
+                moved = moved.max(center.distance(mean));
+                *center = mean;
+            }
+        }
+        if moved < 1e-4 {
+            break;
+        }
+    }
+
+    centers.into_iter()
+        .map(|center| gamut_map(Oklcha::from(Oklaba::new(center.x, center.y, center.z, 1.0))))
+        .collect()
+}
+
+/// k-means++: each new center is picked with odds growing with its squared distance from the ones already picked.
+fn seed_centers(samples: &[Vec3], count: usize, rng: &mut StdRng) -> Vec<Vec3> {
+    let mut centers = vec![samples[rng.random_range(0..samples.len())]];
+    while centers.len() < count {
+        let weights: Vec<f32> = samples.iter()
+            .map(|sample| centers.iter().map(|center| center.distance_squared(*sample)).fold(f32::INFINITY, f32::min))
+            .collect();
+        let total: f32 = weights.iter().sum();
+        if total <= 0.0 {
+            // Every sample is already a center.
+            break;
+        }
+
+        let mut pick = rng.random_range(0.0..total);
+        let idx = weights.iter()
+            .position(|weight| {
+                pick -= weight;
+                pick < 0.0
+            })
+            .unwrap_or(samples.len() - 1);
+        centers.push(samples[idx]);
+    }
+    centers
+}
+
+fn nearest(centers: &[Vec3], point: Vec3) -> usize {
+    centers.iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| a.distance_squared(point).total_cmp(&b.distance_squared(point)))
+        .map_or(0, |(idx, _)| idx)
+}
+
+/// Puts the darkest colour first, where palettes keep their background.
+fn darkest_first(mut colors: Vec<Srgba>) -> Vec<Srgba> {
+    colors.sort_by(|a, b| Oklaba::from(*a).lightness.total_cmp(&Oklaba::from(*b).lightness));
+    colors
+}
@@ -0,0 +1,138 @@
+use std::collections::BTreeSet;
+use bevy::prelude::*;
+use bevy_vector_shapes::prelude::*;
+use pdf_writer::types::LineCapStyle;
+use pdf_writer::{Content, Name, Pdf, Rect as PageRect, Ref, TextStr};
+use crate::canvas::Canvas;
+use crate::recording::{RecordedFrame, RecordedShape};
+
+const PT_PER_MM: f32 = 72.0 / 25.4;
+/// How far flattened curves may stray from the real ones, in millimeters on the page.
+const TOLERANCE_MM: f32 = 0.02;
+
+/// What goes in the document info dictionary.
+pub struct PdfInfo<'a> {
+    pub title: &'a str,
+    pub subject: &'a str,
+}
+
+/// A one page PDF of `frame`, on a page `paper` millimeters across.
+/// The canvas is framed the same way a PNG export frames it, and `dpi` sizes shapes drawn with pixel thicknesses.
+pub fn frame_to_pdf(frame: &RecordedFrame, canvas: &Canvas, paper: Vec2, dpi: f32, background: Color, info: &PdfInfo) -> Vec<u8> {
+    let page = paper * PT_PER_MM;
+    let visible_height = canvas.visible_height(paper.x / paper.y);
+    let mm_per_unit = paper.y / visible_height;
+    let page_frame = PageFrame { mm_per_unit, dpi, visible_height };
+
+    // Shapes are drawn back to front, as the depth buffer would have them.
+    let mut shapes: Vec<&RecordedShape> = frame.shapes.iter().collect();
+    shapes.sort_by(|a, b| a.depth().total_cmp(&b.depth()));
+
+    // One graphics state per alpha level, since PDF colors can't carry alpha themselves.
+    let alphas: BTreeSet<u8> = shapes.iter().map(|shape| alpha_level(shape.color)).collect();
+
+    let mut content = Content::new();
+    let background = Srgba::from(background);
+    content.set_fill_rgb(background.red, background.green, background.blue);
+    content.rect(0.0, 0.0, page.x, page.y).fill_nonzero();
+
+    // World units from here on, with the origin in the middle of the page.
+    let pt_per_unit = mm_per_unit * PT_PER_MM;
+    content.transform([pt_per_unit, 0.0, 0.0, pt_per_unit, page.x / 2.0, page.y / 2.0]);
+    for shape in shapes {
+        page_frame.draw(&mut content, shape);
+    }
+
+    let catalog_id = Ref::new(1);
+    let page_tree_id = Ref::new(2);
+    let page_id = Ref::new(3);
+    let content_id = Ref::new(4);
+    let info_id = Ref::new(5);
+    let state_id = |idx: usize| Ref::new(6 + idx as i32);
+
+    let mut pdf = Pdf::new();
+    pdf.catalog(catalog_id).pages(page_tree_id);
+    pdf.pages(page_tree_id).kids([page_id]).count(1);
+    {
+        let mut page_writer = pdf.page(page_id);
+        page_writer
+            .parent(page_tree_id)
+            .media_box(PageRect::new(0.0, 0.0, page.x, page.y))
+            .contents(content_id);
+        let mut resources = page_writer.resources();
+        let mut states = resources.ext_g_states();
+        for (idx, alpha) in alphas.iter().enumerate() {
+            states.pair(Name(state_name(*alpha).as_bytes()), state_id(idx));
+        }
+    }
+    for (idx, alpha) in alphas.iter().enumerate() {
+        let alpha = *alpha as f32 / 255.0;
+        pdf.ext_graphics(state_id(idx)).stroking_alpha(alpha).non_stroking_alpha(alpha);
+    }
+    pdf.stream(content_id, &content.finish());
+    pdf.document_info(info_id)
+        .title(TextStr(info.title))
+        .subject(TextStr(info.subject))
+        .creator(TextStr("procart"));
+    pdf.finish()
+}
+
+/// How world units map onto the page.
+struct PageFrame {
+    mm_per_unit: f32,
+    dpi: f32,
+    visible_height: f32,
+}
+
+impl PageFrame {
+    fn draw(&self, content: &mut Content, shape: &RecordedShape) {
+        let outline = shape.outline(TOLERANCE_MM / self.mm_per_unit);
+        let [first, rest @ ..] = outline.as_slice() else {
+            return;
+        };
+
+        content.set_parameters(Name(state_name(alpha_level(shape.color)).as_bytes()));
+        content.move_to(first.x, first.y);
+        for point in rest {
+            content.line_to(point.x, point.y);
+        }
+
+        let color = Srgba::from(shape.color);
+        if shape.is_filled() {
+            content.set_fill_rgb(color.red, color.green, color.blue);
+            content.fill_nonzero();
+            return;
+        }
+
+        content.set_stroke_rgb(color.red, color.green, color.blue);
+        content.set_line_width(self.line_width(shape));
+        content.set_line_cap(match shape.cap {
+            Cap::None => LineCapStyle::ButtCap,
+            Cap::Square => LineCapStyle::ProjectingSquareCap,
+            Cap::Round => LineCapStyle::RoundCap,
+        });
+        if rest.last().is_some_and(|last| last.distance(*first) < f32::EPSILON) {
+            content.close_and_stroke();
+        } else {
+            content.stroke();
+        }
+    }
+
+    /// Shape thickness in world units. Pixel thicknesses are taken at the export DPI,
+    /// and screen thicknesses as a percentage of the visible height.
+    fn line_width(&self, shape: &RecordedShape) -> f32 {
+        match shape.thickness_type {
+            ThicknessType::World => shape.thickness,
+            ThicknessType::Pixels => shape.thickness / self.dpi * 25.4 / self.mm_per_unit,
+            ThicknessType::Screen => shape.thickness / 100.0 * self.visible_height,
+        }
+    }
+}
+
+fn alpha_level(color: Color) -> u8 {
+    (color.alpha().clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+fn state_name(alpha: u8) -> String {
+    format!("A{}", alpha)
+}
@@ -0,0 +1,456 @@
+use std::f32::consts::PI;
+use std::path::{Path, PathBuf};
+use bevy::app::App;
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContextPass, EguiContexts};
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+use crate::canvas::Canvas;
+use crate::export::{export_path, write_file, ExportSettings};
+use crate::recording::{RecordedShape, Recording, RecordingSet};
+use crate::strokes::{self, Stroke};
+use crate::{ProgramState, UIState};
+
+/// Writes a frame of the active piece as pen plotter instructions.
+pub struct PlotPlugin;
+
+impl Plugin for PlotPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<PlotSettings>()
+            .init_resource::<Plot>()
+            .add_systems(EguiContextPass, params_ui.run_if(not(in_state(ProgramState::MainMenu))))
+            .add_systems(PostUpdate, run_plot.after(RecordingSet))
+        ;
+    }
+}
+
+/// HPGL plotter units are 0.025 mm.
+const HPGL_UNITS_PER_MM: f32 = 40.0;
+/// Shapes fainter than this don't show on screen, so the pen skips them too.
+const MIN_ALPHA: f32 = 0.01;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter)]
+pub enum PlotFormat {
+    Hpgl,
+    Gcode,
+    Svg,
+}
+
+impl PlotFormat {
+    pub fn label(&self) -> &'static str {
+        match self {
+            PlotFormat::Hpgl => "HPGL",
+            PlotFormat::Gcode => "G-code",
+            PlotFormat::Svg => "SVG",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            PlotFormat::Hpgl => "hpgl",
+            PlotFormat::Gcode => "gcode",
+            PlotFormat::Svg => "svg",
+        }
+    }
+}
+
+/// What to do with filled shapes, which a pen can only draw the edge of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter)]
+pub enum FillMode {
+    Skip,
+    Outline,
+    /// Parallel lines across the inside, plus the outline.
+    Hatch,
+}
+
+#[derive(Resource)]
+pub struct PlotSettings {
+    /// Furthest a flattened curve may stray from the real one, in millimeters.
+    pub tolerance: f32,
+    /// Blank border around the drawing, in millimeters.
+    pub margin: f32,
+    pub fills: FillMode,
+    /// Direction of hatch lines, in radians from the x axis.
+    pub hatch_angle: f32,
+    /// Gap between hatch lines, in millimeters.
+    pub hatch_spacing: f32,
+    /// Hatch a second time at right angles to the first.
+    pub crosshatch: bool,
+    /// Drawing speed in millimeters per minute.
+    pub feed_rate: f32,
+    /// Pen up travel speed in millimeters per minute.
+    pub travel_rate: f32,
+    /// G-code Z height with the pen lifted.
+    pub pen_up: f32,
+    /// G-code Z height with the pen on the paper.
+    pub pen_down: f32,
+    /// Line width in SVG output, in millimeters.
+    pub pen_width: f32,
+    /// Join strokes whose ends touch, so the pen doesn't lift between them.
+    pub merge: bool,
+    /// Reorder strokes to cut down on pen-up travel.
+    pub optimize: bool,
+    /// One file, or SVG layer, per color so each pen can be swapped in turn.
+    pub split_colors: bool,
+}
+
+impl Default for PlotSettings {
+    fn default() -> Self {
+        Self {
+            tolerance: 0.1,
+            margin: 10.0,
+            fills: FillMode::Outline,
+            hatch_angle: PI / 4.0,
+            hatch_spacing: 1.0,
+            crosshatch: false,
+            feed_rate: 1500.0,
+            travel_rate: 3000.0,
+            pen_up: 5.0,
+            pen_down: 0.0,
+            pen_width: 0.3,
+            merge: true,
+            optimize: true,
+            split_colors: true,
+        }
+    }
+}
+
+impl PlotSettings {
+    /// Turns recorded shapes into pen strokes in millimeters on `paper`, with the canvas
+    /// fitted inside the margins and anything outside it cut off.
+    pub fn strokes(&self, shapes: &[RecordedShape], canvas: &Canvas, paper: Vec2) -> Vec<Stroke> {
+        let area = (paper - Vec2::splat(self.margin * 2.0)).max(Vec2::ONE);
+        let mm_per_unit = (area / canvas.size()).min_element();
+        let bounds = Rect::from_center_half_size(paper / 2.0, canvas.half_size() * mm_per_unit);
+
+        let mut strokes = Vec::new();
+        for shape in shapes {
+            let filled = shape.is_filled();
+            if shape.color.alpha() < MIN_ALPHA || (filled && self.fills == FillMode::Skip) {
+                continue;
+            }
+            let color = Srgba::from(shape.color);
+            let outline: Vec<Vec2> = shape.outline(self.tolerance / mm_per_unit)
+                .into_iter()
+                .map(|point| point * mm_per_unit + paper / 2.0)
+                .collect();
+
+            let mut lines = Vec::new();
+            if filled && self.fills == FillMode::Hatch {
+                lines.extend(strokes::hatch(&outline, self.hatch_angle, self.hatch_spacing));
+                if self.crosshatch {
+                    lines.extend(strokes::hatch(&outline, self.hatch_angle + PI / 2.0, self.hatch_spacing));
+                }
+            }
+            lines.push(outline);
+            for line in lines {
+                strokes.extend(clip(&line, bounds).into_iter().map(|points| Stroke { color, points }));
+            }
+        }
+        strokes
+    }
+
+    /// Splits strokes into one layer per pen if asked to, then merges and orders each layer.
+    pub fn layers(&self, strokes: Vec<Stroke>) -> Vec<Vec<Stroke>> {
+        let layers = if self.split_colors { strokes::split_by_color(strokes) } else { vec![strokes] };
+        layers.into_iter()
+            .map(|layer| {
+                let layer = if self.merge { strokes::merge(layer, self.tolerance) } else { layer };
+                // Plotters start and finish at the paper's origin.
+                if self.optimize { strokes::order(layer, Vec2::ZERO) } else { layer }
+            })
+            .collect()
+    }
+
+    pub fn hpgl(&self, strokes: &[Stroke]) -> String {
+        let units = |point: Vec2| (point * HPGL_UNITS_PER_MM).round().as_ivec2();
+
+        // VS takes centimeters per second. Some plotters read VS0 as full speed, so it never rounds down to that.
+        let mut out = format!("IN;SP1;VS{:.1};\n", (self.feed_rate / 600.0).max(0.1));
+        for stroke in strokes {
+            let [first, rest @ ..] = stroke.points.as_slice() else {
+                continue;
+            };
+            let start = units(*first);
+            out += &format!("PU{},{};", start.x, start.y);
+            let points: Vec<String> = rest.iter()
+                .map(|point| {
+                    let point = units(*point);
+                    format!("{},{}", point.x, point.y)
+                })
+                .collect();
+            out += &format!("PD{};\n", points.join(","));
+        }
+        out += "PU;SP0;\n";
+        out
+    }
+
+    pub fn gcode(&self, strokes: &[Stroke], paper: Vec2) -> String {
+        let mut out = format!("; Paper {:.0} x {:.0} mm\nG21\nG90\nG0 Z{:.2}\n", paper.x, paper.y, self.pen_up);
+        for stroke in strokes {
+            let [first, rest @ ..] = stroke.points.as_slice() else {
+                continue;
+            };
+            out += &format!("G0 X{:.3} Y{:.3} F{:.0}\n", first.x, first.y, self.travel_rate);
+            out += &format!("G1 Z{:.2} F{:.0}\n", self.pen_down, self.feed_rate);
+            for point in rest {
+                out += &format!("G1 X{:.3} Y{:.3}\n", point.x, point.y);
+            }
+            out += &format!("G0 Z{:.2}\n", self.pen_up);
+        }
+        out += "G0 X0 Y0\nM2\n";
+        out
+    }
+
+    /// Sized in millimeters, with each layer as an Inkscape layer named after its pen.
+    pub fn svg(&self, layers: &[Vec<Stroke>], paper: Vec2) -> String {
+        let mut out = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" xmlns:inkscape=\"http://www.inkscape.org/namespaces/inkscape\" \
+             width=\"{w}mm\" height=\"{h}mm\" viewBox=\"0 0 {w} {h}\">\n",
+            w = paper.x,
+            h = paper.y,
+        );
+        for (idx, layer) in layers.iter().enumerate() {
+            let Some(first) = layer.first() else {
+                continue;
+            };
+            out += &format!(
+                "<g inkscape:groupmode=\"layer\" id=\"layer{n}\" inkscape:label=\"{n} #{pen}\" \
+                 fill=\"none\" stroke=\"#{pen}\" stroke-width=\"{width}\" stroke-linecap=\"round\" stroke-linejoin=\"round\">\n",
+                n = idx + 1,
+                pen = first.pen_hex(),
+                width = self.pen_width,
+            );
+            for stroke in layer {
+                // SVG runs y down the page.
+                let points: Vec<String> = stroke.points.iter()
+                    .map(|point| format!("{:.3},{:.3}", point.x, paper.y - point.y))
+                    .collect();
+                out += &format!("<polyline points=\"{}\"/>\n", points.join(" "));
+            }
+            out += "</g>\n";
+        }
+        out += "</svg>\n";
+        out
+    }
+
+    /// Writes the layers to `path`, or to one file per layer next to it when they can't share a file.
+    fn write(&self, format: PlotFormat, layers: &[Vec<Stroke>], paper: Vec2, path: &Path) -> std::io::Result<Vec<PathBuf>> {
+        if format == PlotFormat::Svg || layers.len() <= 1 {
+            let contents = match format {
+                PlotFormat::Hpgl => self.hpgl(&layers.concat()),
+                PlotFormat::Gcode => self.gcode(&layers.concat(), paper),
+                PlotFormat::Svg => self.svg(layers, paper),
+            };
+            write_file(path, &contents)?;
+            return Ok(vec![path.to_path_buf()]);
+        }
+
+        let stem = path.file_stem().map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+        let mut paths = Vec::new();
+        for (idx, layer) in layers.iter().enumerate() {
+            let name = format!("{}-{}-{}.{}", stem, idx + 1, layer[0].pen_hex(), format.extension());
+            let layer_path = path.with_file_name(name);
+            let contents = match format {
+                PlotFormat::Hpgl => self.hpgl(layer),
+                PlotFormat::Gcode => self.gcode(layer, paper),
+                PlotFormat::Svg => unreachable!("SVG keeps every layer in one file"),
+            };
+            write_file(&layer_path, &contents)?;
+            paths.push(layer_path);
+        }
+        Ok(paths)
+    }
+}
+
+/// Splits `points` into the runs that lie inside `bounds`, cutting segments where they cross its edge.
+fn clip(points: &[Vec2], bounds: Rect) -> Vec<Vec<Vec2>> {
+    let mut runs = Vec::new();
+    let mut run = Vec::new();
+    for segment in points.windows(2) {
+        let Some((start, end)) = clip_segment(segment[0], segment[1], bounds) else {
+            end_run(&mut runs, &mut run);
+            continue;
+        };
+        if run.last().is_some_and(|last: &Vec2| last.distance_squared(start) > 1e-10) {
+            end_run(&mut runs, &mut run);
+        }
+        if run.is_empty() {
+            run.push(start);
+        }
+        run.push(end);
+    }
+    end_run(&mut runs, &mut run);
+    runs
+}
+
+fn end_run(runs: &mut Vec<Vec<Vec2>>, run: &mut Vec<Vec2>) {
+    if run.len() > 1 {
+        runs.push(std::mem::take(run));
+    }
+    run.clear();
+}
+
+/// Liang-Barsky clipping of the segment from `start` to `end`.
+fn clip_segment(start: Vec2, end: Vec2, bounds: Rect) -> Option<(Vec2, Vec2)> {
+    let delta = end - start;
+    let (mut enter, mut exit) = (0.0_f32, 1.0_f32);
+    for (p, q) in [
+        (-delta.x, start.x - bounds.min.x),
+        (delta.x, bounds.max.x - start.x),
+        (-delta.y, start.y - bounds.min.y),
+        (delta.y, bounds.max.y - start.y),
+    ] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return None;
+            }
+            continue;
+        }
+        let t = q / p;
+        if p < 0.0 {
+            enter = enter.max(t);
+        } else {
+            exit = exit.min(t);
+        }
+        if enter > exit {
+            return None;
+        }
+    }
+    Some((start + delta * enter, start + delta * exit))
+}
+
+/// A plot waiting on the next frame to be recorded.
+#[derive(Resource, Default)]
+struct Plot {
+    pending: Option<PlotFormat>,
+    status: Option<String>,
+}
+
+/// Asks for a frame to be recorded once a plot is wanted, then writes it out once it has been drawn.
+fn run_plot(
+    mut plot: ResMut<Plot>,
+    mut recording: ResMut<Recording>,
+    settings: Res<PlotSettings>,
+    export: Res<ExportSettings>,
+    canvas: Res<Canvas>,
+    program_state: Res<State<ProgramState>>,
+) {
+    let Some(format) = plot.pending else {
+        return;
+    };
+    let Some(frame) = recording.frame() else {
+        recording.request();
+        return;
+    };
+    plot.pending = None;
+    let Some(paper) = export.physical_mm() else {
+        return;
+    };
+
+    let layers = settings.layers(settings.strokes(&frame.shapes, &canvas, paper));
+    let count: usize = layers.iter().map(Vec::len).sum();
+    let travel: f32 = layers.iter().map(|layer| strokes::travel(layer, Vec2::ZERO)).sum();
+    let path = export_path(&export.directory, program_state.get(), format.extension());
+    plot.status = Some(match settings.write(format, &layers, paper, &path) {
+        Ok(paths) if paths.len() == 1 => format!(
+            "Saved {} strokes to {}, {:.2} m of pen-up travel", count, path.display(), travel / 1000.0,
+        ),
+        Ok(paths) => format!(
+            "Saved {} strokes to {} files in {}, {:.2} m of pen-up travel",
+            count, paths.len(), export.directory, travel / 1000.0,
+        ),
+        Err(e) => format!("Plot failed: {}", e),
+    });
+}
+
+fn params_ui(
+    mut contexts: EguiContexts,
+    ui_state: Res<UIState>,
+    export: Res<ExportSettings>,
+    mut settings: ResMut<PlotSettings>,
+    mut plot: ResMut<Plot>,
+) {
+    if !ui_state.params_panel {
+        return;
+    }
+
+    egui::Window::new("Plot").show(contexts.ctx_mut(), |ui| {
+        let paper = export.physical_mm();
+        match paper {
+            Some(mm) => ui.label(format!("Paper {:.0} x {:.0} mm, from the Export panel", mm.x, mm.y)),
+            None => ui.label("Pick a paper size in the Export panel to plot."),
+        };
+
+        ui.heading("Shapes");
+        ui.add(egui::Slider::new(&mut settings.tolerance, 0.01..=1.0).logarithmic(true).text("Tolerance (mm)"));
+        ui.add(egui::Slider::new(&mut settings.margin, 0.0..=50.0).text("Margin (mm)"));
+        ui.horizontal(|ui| {
+            ui.label("Fills");
+            for fills in FillMode::iter() {
+                ui.radio_value(&mut settings.fills, fills, format!("{:?}", fills));
+            }
+        });
+        if settings.fills == FillMode::Hatch {
+            ui.add(egui::Slider::new(&mut settings.hatch_angle, 0.0..=PI).text("Hatch Angle"));
+            ui.add(egui::Slider::new(&mut settings.hatch_spacing, 0.2..=10.0).logarithmic(true).text("Hatch Spacing (mm)"));
+            ui.checkbox(&mut settings.crosshatch, "Crosshatch");
+        }
+
+        ui.heading("Paths");
+        ui.checkbox(&mut settings.merge, "Merge touching strokes");
+        ui.checkbox(&mut settings.optimize, "Minimize pen-up travel");
+        ui.checkbox(&mut settings.split_colors, "One pen per color");
+
+        ui.heading("Pen");
+        ui.add(egui::Slider::new(&mut settings.feed_rate, 100.0..=10000.0).text("Feed (mm/min)"));
+        ui.add(egui::Slider::new(&mut settings.travel_rate, 100.0..=20000.0).text("Travel (mm/min)"));
+        ui.add(egui::Slider::new(&mut settings.pen_up, -10.0..=20.0).text("Pen Up Z"));
+        ui.add(egui::Slider::new(&mut settings.pen_down, -10.0..=20.0).text("Pen Down Z"));
+        ui.add(egui::Slider::new(&mut settings.pen_width, 0.05..=2.0).text("SVG Pen Width (mm)"));
+
+        ui.horizontal(|ui| {
+            for format in PlotFormat::iter() {
+                let enabled = paper.is_some() && plot.pending.is_none();
+                if ui.add_enabled(enabled, egui::Button::new(format!("Export {}", format.label()))).clicked() {
+                    plot.pending = Some(format);
+                    plot.status = None;
+                }
+            }
+        });
+        if let Some(status) = &plot.status {
+            ui.label(status);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clip_segment_cuts_at_the_bounds() {
+        let bounds = Rect::from_center_half_size(Vec2::ZERO, Vec2::ONE);
+        let (start, end) = clip_segment(Vec2::new(-3.0, 0.0), Vec2::new(3.0, 0.0), bounds).unwrap();
+        assert!(start.distance(Vec2::new(-1.0, 0.0)) < 1e-5);
+        assert!(end.distance(Vec2::new(1.0, 0.0)) < 1e-5);
+
+        let (start, end) = clip_segment(Vec2::ZERO, Vec2::new(2.0, 2.0), bounds).unwrap();
+        assert_eq!(start, Vec2::ZERO);
+        assert!(end.distance(Vec2::ONE) < 1e-5);
+    }
+
+    #[test]
+    fn clip_segment_drops_what_misses() {
+        let bounds = Rect::from_center_half_size(Vec2::ZERO, Vec2::ONE);
+        assert!(clip_segment(Vec2::new(-3.0, 2.0), Vec2::new(3.0, 2.0), bounds).is_none());
+    }
+
+    #[test]
+    fn clip_splits_runs_that_leave_and_come_back() {
+        let bounds = Rect::from_center_half_size(Vec2::ZERO, Vec2::ONE);
+        let points = [Vec2::new(-0.5, 0.0), Vec2::new(0.0, 3.0), Vec2::new(0.5, 0.0)];
+        assert_eq!(clip(&points, bounds).len(), 2);
+    }
+}
@@ -0,0 +1,293 @@
+use std::f32::consts::{PI, TAU};
+use std::fmt::Debug;
+use std::ops::{Deref, DerefMut};
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy_vector_shapes::prelude::*;
+use crate::vision::VisionPreview;
+
+/// Records a frame of shapes whenever something asks for one.
+pub struct RecordingPlugin;
+
+impl Plugin for RecordingPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<Recording>()
+            .add_systems(PostUpdate, cycle_recording.in_set(RecordingSet))
+        ;
+    }
+}
+
+/// Systems that read `Recording::frame` should run after this.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RecordingSet;
+
+/// A `ShapePainter` that also keeps a copy of every shape drawn while a recording is running,
+/// so a frame can be written out as vectors instead of pixels.
+/// Colors pass through the vision preview on their way to the screen and the recording.
+#[derive(SystemParam)]
+pub struct Painter<'w, 's> {
+    shapes: ShapePainter<'w, 's>,
+    recording: ResMut<'w, Recording>,
+    vision: Res<'w, VisionPreview>,
+}
+
+impl<'w, 's> Deref for Painter<'w, 's> {
+    type Target = ShapePainter<'w, 's>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.shapes
+    }
+}
+
+impl DerefMut for Painter<'_, '_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.shapes
+    }
+}
+
+impl Painter<'_, '_> {
+    pub fn line(&mut self, start: Vec3, end: Vec3) -> &mut Self {
+        self.record(ShapeKind::Line { start, end });
+        self.previewed(|shapes| {
+            shapes.line(start, end);
+        });
+        self
+    }
+
+    pub fn circle(&mut self, radius: f32) -> &mut Self {
+        self.record(ShapeKind::Circle { radius });
+        self.previewed(|shapes| {
+            shapes.circle(radius);
+        });
+        self
+    }
+
+    pub fn arc(&mut self, radius: f32, start_angle: f32, end_angle: f32) -> &mut Self {
+        self.record(ShapeKind::Arc { radius, start_angle, end_angle });
+        self.previewed(|shapes| {
+            shapes.arc(radius, start_angle, end_angle);
+        });
+        self
+    }
+
+    pub fn rect(&mut self, size: Vec2) -> &mut Self {
+        self.record(ShapeKind::Rect { size });
+        self.previewed(|shapes| {
+            shapes.rect(size);
+        });
+        self
+    }
+
+    pub fn ngon(&mut self, sides: f32, radius: f32) -> &mut Self {
+        self.record(ShapeKind::Ngon { sides, radius });
+        self.previewed(|shapes| {
+            shapes.ngon(sides, radius);
+        });
+        self
+    }
+
+    /// Notes the piece's parameters alongside the recorded shapes, for file metadata.
+    pub fn describe(&mut self, params: &impl Debug) {
+        if self.recording.active {
+            self.recording.params.push(format!("{:#?}", params));
+        }
+    }
+
+    /// Draws with the painter's color as the vision preview shows it, leaving the color as it was.
+    fn previewed(&mut self, draw: impl FnOnce(&mut ShapePainter<'_, '_>)) {
+        let color = self.shapes.color;
+        self.shapes.color = self.vision.for_screen(color);
+        draw(&mut self.shapes);
+        self.shapes.color = color;
+    }
+
+    fn record(&mut self, kind: ShapeKind) {
+        if !self.recording.active {
+            return;
+        }
+        let shape = RecordedShape {
+            kind,
+            transform: self.shapes.transform,
+            color: self.vision.for_export(self.shapes.color),
+            hollow: self.shapes.hollow,
+            thickness: self.shapes.thickness,
+            thickness_type: self.shapes.thickness_type,
+            cap: self.shapes.cap,
+            corner_radii: self.shapes.corner_radii,
+            roundness: self.shapes.roundness,
+        };
+        self.recording.shapes.push(shape);
+    }
+}
+
+/// Shapes drawn through `Painter` during a recorded frame.
+#[derive(Resource, Default)]
+pub struct Recording {
+    requested: bool,
+    active: bool,
+    shapes: Vec<RecordedShape>,
+    params: Vec<String>,
+    frame: Option<RecordedFrame>,
+}
+
+impl Recording {
+    /// Asks for the next frame to be recorded. It shows up in `frame` once it has been drawn.
+    pub fn request(&mut self) {
+        if !self.active {
+            self.requested = true;
+        }
+    }
+
+    /// The frame that was just recorded, for the one update after it was drawn.
+    pub fn frame(&self) -> Option<&RecordedFrame> {
+        self.frame.as_ref()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RecordedFrame {
+    pub shapes: Vec<RecordedShape>,
+    /// What the piece passed to `Painter::describe`.
+    pub params: Vec<String>,
+}
+
+/// Wraps up the frame drawn this update, or starts recording the next one if asked to.
+fn cycle_recording(mut recording: ResMut<Recording>) {
+    recording.frame = None;
+    if recording.active {
+        recording.active = false;
+        recording.frame = Some(RecordedFrame {
+            shapes: std::mem::take(&mut recording.shapes),
+            params: std::mem::take(&mut recording.params),
+        });
+    } else if recording.requested {
+        recording.requested = false;
+        recording.active = true;
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ShapeKind {
+    Line { start: Vec3, end: Vec3 },
+    Circle { radius: f32 },
+    Arc { radius: f32, start_angle: f32, end_angle: f32 },
+    Rect { size: Vec2 },
+    Ngon { sides: f32, radius: f32 },
+}
+
+/// One painter call, along with the painter settings it was drawn with.
+#[derive(Debug, Clone)]
+pub struct RecordedShape {
+    pub kind: ShapeKind,
+    pub transform: Transform,
+    pub color: Color,
+    pub hollow: bool,
+    pub thickness: f32,
+    pub thickness_type: ThicknessType,
+    pub cap: Cap,
+    pub corner_radii: Vec4,
+    pub roundness: f32,
+}
+
+impl RecordedShape {
+    /// Lines are always strokes, everything else is filled unless the painter was hollow.
+    pub fn is_filled(&self) -> bool {
+        !self.hollow && !matches!(self.kind, ShapeKind::Line { .. })
+    }
+
+    /// The shape's outline in the world's xy plane, with curves flattened into segments
+    /// that stray at most `tolerance` world units from them. Closed outlines end where they start.
+    pub fn outline(&self, tolerance: f32) -> Vec<Vec2> {
+        let tolerance = tolerance / self.transform.scale.abs().max_element().max(f32::EPSILON);
+        let local = match self.kind {
+            ShapeKind::Line { start, end } => return vec![self.to_world(start), self.to_world(end)],
+            ShapeKind::Circle { radius } => arc_points(Vec2::ZERO, radius, 0.0, TAU, tolerance),
+            ShapeKind::Arc { radius, start_angle, end_angle } => {
+                let mut points = arc_points(Vec2::ZERO, radius, start_angle, end_angle, tolerance);
+                // A filled arc is a pie slice.
+                if !self.hollow {
+                    points.insert(0, Vec2::ZERO);
+                    points.push(Vec2::ZERO);
+                }
+                points
+            }
+            ShapeKind::Rect { size } => rounded_rect(size, self.corner_radii, tolerance),
+            ShapeKind::Ngon { sides, radius } => rounded_ngon(sides, radius, self.roundness, tolerance),
+        };
+        local.into_iter().map(|point| self.to_world(point.extend(0.0))).collect()
+    }
+
+    /// World depth, for drawing shapes back to front.
+    pub fn depth(&self) -> f32 {
+        let local = match self.kind {
+            ShapeKind::Line { start, end } => (start + end) / 2.0,
+            _ => Vec3::ZERO,
+        };
+        self.transform.transform_point(local).z
+    }
+
+    fn to_world(&self, point: Vec3) -> Vec2 {
+        self.transform.transform_point(point).truncate()
+    }
+}
+
+/// Point on a circle, with angles running clockwise from +Y the way the arc shader measures them.
+fn on_circle(center: Vec2, radius: f32, angle: f32) -> Vec2 {
+    center + Vec2::new(angle.sin(), angle.cos()) * radius
+}
+
+fn arc_points(center: Vec2, radius: f32, start_angle: f32, end_angle: f32, tolerance: f32) -> Vec<Vec2> {
+    // Widest angle a chord can span while its middle stays within tolerance of the arc.
+    let step = 2.0 * (1.0 - tolerance / radius).clamp(-1.0, 1.0).acos();
+    let segments = ((end_angle - start_angle).abs() / step.max(0.001)).ceil().clamp(1.0, 1024.0) as usize;
+    (0..=segments)
+        .map(|i| on_circle(center, radius, start_angle + (end_angle - start_angle) * i as f32 / segments as f32))
+        .collect()
+}
+
+/// Corner radii are top right, bottom right, top left and bottom left, as in the rect shader.
+fn rounded_rect(size: Vec2, corner_radii: Vec4, tolerance: f32) -> Vec<Vec2> {
+    let half = size.abs() / 2.0;
+    let radii = corner_radii.clamp(Vec4::ZERO, Vec4::splat(half.min_element()));
+    let corners = [
+        (Vec2::new(half.x, half.y), radii.x, 0.0),
+        (Vec2::new(half.x, -half.y), radii.y, PI / 2.0),
+        (Vec2::new(-half.x, -half.y), radii.w, PI),
+        (Vec2::new(-half.x, half.y), radii.z, PI * 1.5),
+    ];
+
+    let mut points = Vec::new();
+    for (corner, radius, start_angle) in corners {
+        if radius <= 0.0 {
+            points.push(corner);
+            continue;
+        }
+        let center = corner - corner.signum() * radius;
+        points.extend(arc_points(center, radius, start_angle, start_angle + PI / 2.0, tolerance));
+    }
+    points.push(points[0]);
+    points
+}
+
+/// A regular polygon with its first corner pointing up and each corner rounded off by `roundness`.
+fn rounded_ngon(sides: f32, radius: f32, roundness: f32, tolerance: f32) -> Vec<Vec2> {
+    let sides = sides.round().max(3.0) as usize;
+    let half_angle = PI / sides as f32;
+    let apothem = radius * half_angle.cos();
+    let roundness = roundness.clamp(0.0, apothem);
+
+    let mut points = Vec::new();
+    for i in 0..sides {
+        let angle = TAU * i as f32 / sides as f32;
+        if roundness <= 0.0 {
+            points.push(on_circle(Vec2::ZERO, radius, angle));
+            continue;
+        }
+        // The corner circle touches both neighbouring edges, so it turns between their normals.
+        let center = on_circle(Vec2::ZERO, radius - roundness / half_angle.cos(), angle);
+        points.extend(arc_points(center, roundness, angle - half_angle, angle + half_angle, tolerance));
+    }
+    points.push(points[0]);
+    points
+}
@@ -0,0 +1,247 @@
+use bevy::color::{ColorToPacked, Srgba};
+use bevy::math::Vec2;
+use crate::common::SpatialGrid;
+
+/// How many passes of 2-opt to run at most, each one checking every stroke against the ones after it.
+const TWO_OPT_PASSES: usize = 8;
+/// How far ahead of a stroke 2-opt looks for one to swap with. Keeps big plots from taking minutes.
+const TWO_OPT_WINDOW: usize = 400;
+
+/// One pen-down run, in millimeters on the paper.
+#[derive(Debug, Clone)]
+pub struct Stroke {
+    pub color: Srgba,
+    /// Always at least two points.
+    pub points: Vec<Vec2>,
+}
+
+impl Stroke {
+    pub fn start(&self) -> Vec2 {
+        self.points[0]
+    }
+
+    pub fn end(&self) -> Vec2 {
+        self.points[self.points.len() - 1]
+    }
+
+    fn reverse(&mut self) {
+        self.points.reverse();
+    }
+
+    /// The color as a pen sees it, without alpha.
+    pub fn pen(&self) -> [u8; 3] {
+        self.color.to_u8_array_no_alpha()
+    }
+
+    pub fn pen_hex(&self) -> String {
+        let [r, g, b] = self.pen();
+        format!("{:02x}{:02x}{:02x}", r, g, b)
+    }
+}
+
+/// Groups strokes by pen, in the order each pen is first used.
+pub fn split_by_color(strokes: Vec<Stroke>) -> Vec<Vec<Stroke>> {
+    let mut layers: Vec<Vec<Stroke>> = Vec::new();
+    for stroke in strokes {
+        match layers.iter_mut().find(|layer| layer[0].pen() == stroke.pen()) {
+            Some(layer) => layer.push(stroke),
+            None => layers.push(vec![stroke]),
+        }
+    }
+    layers
+}
+
+/// Joins strokes of the same pen whose ends are within `tolerance` of each other, so the pen stays down across them.
+pub fn merge(strokes: Vec<Stroke>, tolerance: f32) -> Vec<Stroke> {
+    let mut grid = SpatialGrid::new(tolerance);
+    for (idx, stroke) in strokes.iter().enumerate() {
+        grid.insert(idx, stroke.start());
+        grid.insert(idx, stroke.end());
+    }
+
+    let mut used = vec![false; strokes.len()];
+    let mut merged = Vec::new();
+    for idx in 0..strokes.len() {
+        if used[idx] {
+            continue;
+        }
+        used[idx] = true;
+
+        let mut chain = strokes[idx].clone();
+        // Grow the end, then flip and grow what was the start. Flipping twice leaves it the right way round.
+        for _ in 0..2 {
+            loop {
+                let end = chain.end();
+                let touching = grid.nearby(end).find(|&other| {
+                    !used[other]
+                        && strokes[other].pen() == chain.pen()
+                        && (strokes[other].start().distance(end) <= tolerance || strokes[other].end().distance(end) <= tolerance)
+                });
+                let Some(other) = touching else {
+                    break;
+                };
+                used[other] = true;
+
+                let mut next = strokes[other].clone();
+                if next.start().distance(end) > tolerance {
+                    next.reverse();
+                }
+                chain.points.extend_from_slice(&next.points[1..]);
+            }
+            chain.reverse();
+        }
+        merged.push(chain);
+    }
+    merged
+}
+
+/// Lines `spacing` apart at `angle` across the inside of the closed `outline`, using the even-odd rule.
+/// Lines sit on a grid fixed to the paper, so neighbouring shapes hatch in step.
+pub fn hatch(outline: &[Vec2], angle: f32, spacing: f32) -> Vec<Vec<Vec2>> {
+    let spacing = spacing.max(0.01);
+    // Turn the outline so hatch lines run along x, find where each line crosses it, then turn back.
+    let rotation = Vec2::from_angle(-angle);
+    let turned: Vec<Vec2> = outline.iter().map(|point| rotation.rotate(*point)).collect();
+    let (min_y, max_y) = turned.iter().fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), point| {
+        (min.min(point.y), max.max(point.y))
+    });
+    let back = Vec2::from_angle(angle);
+
+    let mut lines = Vec::new();
+    let mut row = (min_y / spacing).ceil();
+    while row * spacing <= max_y {
+        let y = row * spacing;
+        let mut crossings: Vec<f32> = turned.windows(2)
+            .filter(|edge| (edge[0].y <= y) != (edge[1].y <= y))
+            .map(|edge| edge[0].x + (y - edge[0].y) / (edge[1].y - edge[0].y) * (edge[1].x - edge[0].x))
+            .collect();
+        crossings.sort_by(f32::total_cmp);
+        for pair in crossings.chunks_exact(2) {
+            lines.push(vec![back.rotate(Vec2::new(pair[0], y)), back.rotate(Vec2::new(pair[1], y))]);
+        }
+        row += 1.0;
+    }
+    lines
+}
+
+/// Orders strokes, flipping them where it helps, so the pen travels as little as possible while lifted.
+/// Starts with the nearest stroke each time, then improves on that with 2-opt.
+pub fn order(strokes: Vec<Stroke>, origin: Vec2) -> Vec<Stroke> {
+    let mut route = nearest_neighbour(strokes, origin);
+    two_opt(&mut route, origin);
+    route
+}
+
+/// Distance the pen moves while lifted, starting from `origin`.
+pub fn travel(strokes: &[Stroke], origin: Vec2) -> f32 {
+    let mut pos = origin;
+    let mut distance = 0.0;
+    for stroke in strokes {
+        distance += pos.distance(stroke.start());
+        pos = stroke.end();
+    }
+    distance
+}
+
+fn nearest_neighbour(mut strokes: Vec<Stroke>, origin: Vec2) -> Vec<Stroke> {
+    let mut route = Vec::with_capacity(strokes.len());
+    let mut pos = origin;
+    while !strokes.is_empty() {
+        let mut best = (0, false, f32::INFINITY);
+        for (idx, stroke) in strokes.iter().enumerate() {
+            let to_start = pos.distance_squared(stroke.start());
+            let to_end = pos.distance_squared(stroke.end());
+            if to_start < best.2 {
+                best = (idx, false, to_start);
+            }
+            if to_end < best.2 {
+                best = (idx, true, to_end);
+            }
+        }
+
+        let mut stroke = strokes.swap_remove(best.0);
+        if best.1 {
+            stroke.reverse();
+        }
+        pos = stroke.end();
+        route.push(stroke);
+    }
+    route
+}
+
+/// Reverses runs of the route wherever that shortens the jumps into and out of them.
+/// The strokes inside a run are flipped too, so the travel between them stays the same.
+fn two_opt(route: &mut [Stroke], origin: Vec2) {
+    for _ in 0..TWO_OPT_PASSES {
+        let mut improved = false;
+        for i in 0..route.len() {
+            let before = if i == 0 { origin } else { route[i - 1].end() };
+            for j in i + 1..route.len().min(i + TWO_OPT_WINDOW) {
+                let after = route.get(j + 1).map(Stroke::start);
+                let current = before.distance(route[i].start()) + after.map_or(0.0, |after| route[j].end().distance(after));
+                let reversed = before.distance(route[j].end()) + after.map_or(0.0, |after| route[i].start().distance(after));
+                if reversed < current - 1e-4 {
+                    route[i..=j].reverse();
+                    route[i..=j].iter_mut().for_each(Stroke::reverse);
+                    improved = true;
+                }
+            }
+        }
+        if !improved {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stroke(points: &[(f32, f32)]) -> Stroke {
+        Stroke {
+            color: Srgba::BLACK,
+            points: points.iter().map(|&(x, y)| Vec2::new(x, y)).collect(),
+        }
+    }
+
+    #[test]
+    fn merge_joins_reversed_ends() {
+        let strokes = vec![
+            stroke(&[(0.0, 0.0), (1.0, 0.0)]),
+            // Runs back towards the first stroke, so it has to be flipped to join.
+            stroke(&[(2.0, 0.0), (1.0, 0.0)]),
+        ];
+        let merged = merge(strokes, 0.01);
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].points.len(), 3);
+        let ends = [merged[0].start(), merged[0].end()];
+        assert!(ends.contains(&Vec2::ZERO) && ends.contains(&Vec2::new(2.0, 0.0)));
+    }
+
+    #[test]
+    fn merge_keeps_pens_apart() {
+        let mut red = stroke(&[(1.0, 0.0), (2.0, 0.0)]);
+        red.color = Srgba::RED;
+        let merged = merge(vec![stroke(&[(0.0, 0.0), (1.0, 0.0)]), red], 0.01);
+        assert_eq!(merged.len(), 2);
+    }
+
+    #[test]
+    fn two_opt_never_increases_travel() {
+        // A zigzag of short strokes, deliberately visited in a poor order.
+        let mut route: Vec<Stroke> = (0..40)
+            .map(|idx| {
+                let x = ((idx * 17) % 40) as f32;
+                let y = ((idx * 7) % 5) as f32;
+                stroke(&[(x, y), (x + 0.5, y + 0.5)])
+            })
+            .collect();
+        let origin = Vec2::ZERO;
+        let before = travel(&route, origin);
+        two_opt(&mut route, origin);
+        assert!(travel(&route, origin) <= before);
+
+        let greedy = nearest_neighbour(route.clone(), origin);
+        assert!(travel(&order(route, origin), origin) <= travel(&greedy, origin));
+    }
+}
@@ -4,11 +4,23 @@ use bevy_vector_shapes::prelude::*;
 use crate::{ProgramState, UIState};
 use std::f32::consts::{PI, TAU};
 use bevy_egui::{egui, EguiContextPass, EguiContexts};
-use crate::common::{CachedRandom, Modifier};
+use rand::prelude::*;
+use crate::common::{color_edit, Modifier, Random};
+use crate::common::noise::Noise;
+use crate::mask::MaskSource;
+use crate::palette::{Palettes, Role};
+use crate::recording::Painter;
 
 #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
 struct LeafSet;
 
+/// Depth of the ground the canopy casts its shadows onto. Leaves are drawn at z=1.1.
+const SHADOW_Z: f32 = 0.9;
+const DAPPLE_Z: f32 = 0.8;
+/// Gusts are a few world units across and change over a couple of seconds.
+const GUST_SCALE: f32 = 0.3;
+const GUST_SPEED: f32 = 0.5;
+
 pub struct LeafArt;
 
 impl Plugin for LeafArt {
@@ -19,18 +31,22 @@ impl Plugin for LeafArt {
                                 LeafSet.run_if(in_state(ProgramState::SummerLeaves)),
                                 ))
             .init_resource::<LeafParams>()
+            .init_resource::<FallingLeaves>()
             .add_systems(EguiContextPass, params_ui)
             .add_systems(
                 Update,
                 (
+                    apply_palette.run_if(resource_changed::<Palettes>),
+                    reseed.run_if(resource_changed::<Random>),
+                    simulate_falling,
                     draw,
-                ).in_set(LeafSet)
+                ).chain().in_set(LeafSet)
             )
         ;
     }
 }
 
-#[derive(Resource)]
+#[derive(Resource, Debug)]
 struct LeafParams {
     show_debug_grid: bool,
     debug_spacing: f32,
@@ -40,6 +56,44 @@ struct LeafParams {
     debug_rotation: f32,
     bounds: Vec2,
     debug_show_bounds: bool,
+    falling: bool,
+    falling_count: usize,
+    gravity: f32,
+    drag: f32,
+    wind: f32,
+    gust: f32,
+    /// Gusts sweep across the bounds, so neighbouring leaves and twigs are pushed together.
+    /// Follows the seed in `Random`.
+    wind_noise: Noise,
+    flutter: f32,
+    tumble: f32,
+    rest_time: f32,
+    show_light: bool,
+    sun_angle: f32,
+    sun_elevation: f32,
+    shadow_alpha: f32,
+    shadow_softness: f32,
+    dapple_count: usize,
+    dapple_size: f32,
+    dapple_alpha: f32,
+    dapple_sway: f32,
+    show_canopy: bool,
+    branch_depth: usize,
+    branch_angle: f32,
+    branch_length: f32,
+    branch_falloff: f32,
+    branch_jitter: f32,
+    branch_thickness: f32,
+    leaf_density: f32,
+    leaf_spread: f32,
+    mask: MaskSource,
+    mask_leaf_count: usize,
+    ground_color: Srgba,
+    leaf_color: Srgba,
+    mask_leaf_color: Srgba,
+    falling_color: Srgba,
+    twig_color: Srgba,
+    light_color: Srgba,
 }
 
 impl Default for LeafParams {
@@ -53,32 +107,210 @@ impl Default for LeafParams {
             debug_rotation: 0.0,
             bounds: Vec2::new(3.0, 3.0),
             debug_show_bounds: true,
+            falling: false,
+            falling_count: 40,
+            gravity: 2.0,
+            drag: 1.5,
+            wind: 0.3,
+            gust: 0.6,
+            wind_noise: Noise::default(),
+            flutter: 1.2,
+            tumble: 3.0,
+            rest_time: 6.0,
+            show_light: true,
+            sun_angle: PI / 4.0,
+            sun_elevation: 0.4,
+            shadow_alpha: 0.35,
+            shadow_softness: 0.08,
+            dapple_count: 24,
+            dapple_size: 0.35,
+            dapple_alpha: 0.25,
+            dapple_sway: 0.3,
+            show_canopy: false,
+            branch_depth: 6,
+            branch_angle: PI / 7.0,
+            branch_length: 0.6,
+            branch_falloff: 0.75,
+            branch_jitter: 0.3,
+            branch_thickness: 0.6,
+            leaf_density: 2.0,
+            leaf_spread: PI / 4.0,
+            mask: MaskSource::default(),
+            mask_leaf_count: 400,
+            ground_color: DARK_OLIVEGREEN.pastel(),
+            leaf_color: SEA_GREEN.pastel(),
+            mask_leaf_color: OLIVE_DRAB.pastel(),
+            falling_color: FOREST_GREEN.pastel(),
+            twig_color: SADDLE_BROWN.pastel(),
+            light_color: LIGHT_YELLOW,
         }
     }
 }
 
 impl LeafParams {
-    fn draw_debug_grid(&self, painter: &mut ShapePainter, rand: Res<CachedRandom>) {
-        painter.set_color(BLACK);
+    /// How hard the wind is gusting at `pos`, roughly -1..1. Gusts drift downwind as they go.
+    fn gust_at(&self, pos: Vec2, seconds: f32) -> f32 {
+        let drift = Vec2::X * self.wind * seconds;
+        self.wind_noise.simplex3(((pos - drift) * GUST_SCALE).extend(seconds * GUST_SPEED))
+    }
+
+    fn draw_debug_grid(&self, painter: &mut Painter, rand: &Random, offset: Vec3) {
+        let rand = rand.stream("debug_grid");
         for i in 0..10 {
             for j in 0..10 {
                 let idx: usize = i * 10 + j;
-                self.draw_leaf(painter, Vec3::new(i as f32 * self.debug_spacing, j as f32 * self.debug_spacing, 1.1), rand.f32(idx) * TAU * self.debug_rotation, idx)
+                self.draw_leaf(painter, Vec3::new(i as f32 * self.debug_spacing, j as f32 * self.debug_spacing, 1.1) + offset, rand.f32(idx) * TAU * self.debug_rotation, idx)
+            }
+        }
+    }
+
+    /// Grows a branch structure up from the bottom of the bounds, with leaves at the twig tips.
+    fn canopy(&self, rand: &Random, seconds: f32) -> Canopy {
+        let rand = rand.stream("canopy");
+        let mut canopy = Canopy::default();
+        let trunk = Twig {
+            start: Vec2::new(0.0, -self.bounds.y),
+            end: Vec2::new(0.0, -self.bounds.y + self.bounds.y * 2.0 * self.branch_length),
+            depth: 0,
+        };
+        self.grow(&mut canopy, &rand, seconds, trunk, PI / 2.0, 1);
+        canopy
+    }
+
+    fn grow(&self, canopy: &mut Canopy, rand: &Random, seconds: f32, twig: Twig, angle: f32, idx: usize) {
+        let length = twig.start.distance(twig.end);
+        let (end, depth) = (twig.end, twig.depth);
+        canopy.twigs.push(twig);
+
+        if depth + 1 >= self.branch_depth {
+            let density = self.leaf_density * self.mask.sample_bounds(end, self.bounds).unwrap_or(1.0);
+            let count = density.floor() as usize
+                + (rand.f32((idx, 0)) < density.fract()) as usize;
+            for i in 0..count {
+                let spread = rand.range((idx, 1 + i), -1.0..1.0) * self.leaf_spread;
+                canopy.leaves.push((end, angle + spread));
+            }
+            return;
+        }
+
+        // Outer twigs sway more in the wind than the trunk does.
+        let sway = self.gust_at(end, seconds) * self.wind * 0.03 * depth as f32;
+        // A stream of their own, as a child's index can be a tip's, whose leaves are keyed by it.
+        let jitters = rand.stream("jitter");
+        for (side, child) in [(-1.0, idx * 2), (1.0, idx * 2 + 1)] {
+            let jitter = jitters.range(child, -1.0..1.0) * self.branch_jitter;
+            let child_angle = angle + side * self.branch_angle * (1.0 + jitter) + sway;
+            let child_length = length * self.branch_falloff * (1.0 + jitter * 0.5);
+            let child_end = (end + Vec2::from_angle(child_angle) * child_length).clamp(-self.bounds, self.bounds);
+            let twig = Twig { start: end, end: child_end, depth: depth + 1 };
+            self.grow(canopy, rand, seconds, twig, child_angle, child);
+        }
+    }
+
+    fn draw_twigs(&self, painter: &mut Painter, canopy: &Canopy) {
+        painter.set_translation(Vec3::ZERO);
+        painter.set_rotation(Quat::IDENTITY);
+        painter.set_color(self.twig_color);
+        painter.cap = Cap::Round;
+        for twig in &canopy.twigs {
+            painter.thickness = self.branch_thickness * self.branch_falloff.powi(twig.depth as i32);
+            painter.line(twig.start.extend(1.0), twig.end.extend(1.0));
+        }
+    }
+
+    fn draw_canopy_leaves(&self, painter: &mut Painter, canopy: &Canopy, offset: Vec3) {
+        for (idx, (pos, rotation)) in canopy.leaves.iter().enumerate() {
+            self.draw_leaf(painter, pos.extend(1.1) + offset, *rotation, idx);
+        }
+    }
+
+    /// Scatters leaves over the bounds, keeping more of them where the mask is bright.
+    fn draw_mask_leaves(&self, painter: &mut Painter, rand: &Random, offset: Vec3) {
+        let rand = rand.stream("mask_leaves");
+        let mut placed = 0;
+        for i in 0..self.mask_leaf_count * 8 {
+            if placed >= self.mask_leaf_count {
+                break;
+            }
+            let pos = (rand.vec2((i, 0)) * 2.0 - 1.0) * self.bounds;
+            let Some(density) = self.mask.sample_bounds(pos, self.bounds) else {
+                return;
+            };
+            if rand.f32((i, 1)) < density {
+                self.draw_leaf(painter, pos.extend(1.1) + offset, rand.f32((i, 2)) * TAU, placed);
+                placed += 1;
             }
         }
     }
 
-    fn draw_leaf(&self, painter: &mut ShapePainter, pos: Vec3, rotation: f32, idx: usize) {
+    /// Direction pointing from the canopy towards the sun.
+    fn sun_direction(&self) -> Vec2 {
+        Vec2::new(self.sun_angle.cos(), self.sun_angle.sin())
+    }
+
+    /// How far a leaf's shadow lands from it, given how high the leaf sits above the ground.
+    fn shadow_offset(&self, height: f32) -> Vec2 {
+        -self.sun_direction() * height / self.sun_elevation.tan().max(0.05)
+    }
+
+    /// Calls `draw_leaves` once per shadow layer with the offset to draw at.
+    /// Layers are spread slightly and share the alpha so the edges come out soft.
+    fn draw_shadows(&self, painter: &mut Painter, mut draw_leaves: impl FnMut(&mut Painter, Vec3)) {
+        const LAYERS: usize = 3;
+        const LEAF_HEIGHT: f32 = 1.1 - SHADOW_Z;
+
+        painter.set_color(DARK_OLIVEGREEN.with_alpha(self.shadow_alpha / LAYERS as f32));
+        for layer in 0..LAYERS {
+            let spread = 1.0 + layer as f32 * self.shadow_softness;
+            let offset = self.shadow_offset(LEAF_HEIGHT) * spread;
+            draw_leaves(painter, offset.extend(SHADOW_Z - 1.1));
+        }
+    }
+
+    /// Patches of light that slip through the canopy and drift about as the wind moves the leaves.
+    fn draw_dapples(&self, painter: &mut Painter, rand: &Random, seconds: f32) {
+        let rand = rand.stream("dapples");
+        painter.set_rotation(Quat::IDENTITY);
+        painter.hollow = false;
+
+        painter.set_color(self.ground_color);
+        painter.set_translation(Vec3::ZERO.with_z(DAPPLE_Z - 0.01));
+        painter.rect(self.bounds * 2.0);
+
+        let sway = self.dapple_sway * (1.0 + self.wind.abs());
+        let drift = self.sun_direction() * 0.1;
+        for i in 0..self.dapple_count {
+            let base = (rand.vec2((i, 0)) * 2.0 - 1.0) * self.bounds;
+            let phase = rand.f32((i, 1)) * TAU;
+            let speed = rand.range((i, 2), 0.5..1.5);
+
+            let shift = Vec2::new(
+                (seconds * speed + phase).sin() + self.wind * 0.5,
+                (seconds * speed * 0.6 + phase).cos() * 0.4,
+            ) * sway;
+            let pos = (base + shift + drift).clamp(-self.bounds, self.bounds);
+            let radius = self.dapple_size * (0.6 + 0.4 * (seconds * speed * 1.3 + phase).sin().abs());
+
+            painter.set_color(self.light_color.with_alpha(self.dapple_alpha));
+            painter.set_translation(pos.extend(DAPPLE_Z));
+            painter.circle(radius);
+            painter.set_color(self.light_color.with_alpha(self.dapple_alpha * 0.5));
+            painter.circle(radius * 1.4);
+        }
+    }
+
+    fn draw_leaf(&self, painter: &mut Painter, pos: Vec3, rotation: f32, idx: usize) {
         let pos = pos + Vec3::new(self.center_offset * rotation.cos(), self.center_offset * rotation.sin(), 0.0);
         painter.set_translation(pos);
         painter.set_rotation(Quat::from_rotation_z(rotation));
         painter.hollow = false;
         painter.thickness = self.line_thickness;
-        painter.line(Vec3::ZERO.with_z(1.0), Vec3::new(self.leaf_size, 0.0, 1.0));
+        // The stem stays level with the leaf, so a shadow's stem can't rise above the leaves casting it.
+        painter.line(Vec3::ZERO, Vec3::new(self.leaf_size, 0.0, 0.0));
         painter.circle(self.leaf_size / 2.0);
     }
 
-    fn draw_bounds(&self, painter: &mut ShapePainter) {
+    fn draw_bounds(&self, painter: &mut Painter) {
         painter.set_color(RED.pastel().with_alpha(0.6));
         painter.set_translation(Vec3::ZERO);
         painter.set_rotation(Quat::from_rotation_z(0.0));
@@ -94,6 +326,8 @@ impl LeafParams {
 fn params_ui(
     mut contexts: EguiContexts,
     mut params: ResMut<LeafParams>,
+    mut palettes: ResMut<Palettes>,
+    mut rand: ResMut<Random>,
     ui_state: Res<UIState>,
     program_state: Res<State<ProgramState>>,
 ) {
@@ -116,17 +350,252 @@ fn params_ui(
         ui.add(egui::Slider::new(&mut params.bounds.x, 0.0..=10.0).text("Bounds X"));
         ui.add(egui::Slider::new(&mut params.bounds.y, 0.0..=10.0).text("Bounds Y"));
         ui.checkbox(&mut params.debug_show_bounds, "Debug show bounds");
+
+        ui.heading("Falling");
+        ui.checkbox(&mut params.falling, "Falling leaves");
+        ui.add(egui::Slider::new(&mut params.falling_count, 1..=300).text("Count"));
+        ui.add(egui::Slider::new(&mut params.gravity, 0.0..=10.0).text("Gravity"));
+        ui.add(egui::Slider::new(&mut params.drag, 0.0..=5.0).text("Drag"));
+        ui.add(egui::Slider::new(&mut params.wind, -3.0..=3.0).text("Wind"));
+        ui.add(egui::Slider::new(&mut params.gust, 0.0..=3.0).text("Gust"));
+        ui.add(egui::Slider::new(&mut params.flutter, 0.0..=5.0).text("Flutter"));
+        ui.add(egui::Slider::new(&mut params.tumble, 0.0..=10.0).text("Tumble"));
+        ui.add(egui::Slider::new(&mut params.rest_time, 0.0..=30.0).text("Rest Time"));
+
+        ui.heading("Canopy");
+        ui.checkbox(&mut params.show_canopy, "Branches");
+        ui.add(egui::Slider::new(&mut params.branch_depth, 1..=10).text("Depth"));
+        ui.add(egui::Slider::new(&mut params.branch_angle, 0.0..=PI / 2.0).text("Angle"));
+        ui.add(egui::Slider::new(&mut params.branch_length, 0.05..=1.0).text("Trunk Length"));
+        ui.add(egui::Slider::new(&mut params.branch_falloff, 0.3..=1.0).text("Falloff"));
+        ui.add(egui::Slider::new(&mut params.branch_jitter, 0.0..=1.0).text("Jitter"));
+        ui.add(egui::Slider::new(&mut params.branch_thickness, 0.01..=2.0).text("Thickness"));
+        ui.add(egui::Slider::new(&mut params.leaf_density, 0.0..=8.0).text("Leaf Density"));
+        ui.add(egui::Slider::new(&mut params.leaf_spread, 0.0..=PI).text("Leaf Spread"));
+
+        ui.heading("Mask");
+        params.mask.ui(ui);
+        ui.add(egui::Slider::new(&mut params.mask_leaf_count, 0..=2000).text("Mask Leaves"));
+
+        ui.heading("Light");
+        ui.checkbox(&mut params.show_light, "Sunlight");
+        ui.add(egui::Slider::new(&mut params.sun_angle, 0.0..=TAU).text("Sun Angle"));
+        ui.add(egui::Slider::new(&mut params.sun_elevation, 0.1..=PI / 2.0).text("Sun Elevation"));
+        ui.add(egui::Slider::new(&mut params.shadow_alpha, 0.0..=1.0).text("Shadow Alpha"));
+        ui.add(egui::Slider::new(&mut params.shadow_softness, 0.0..=0.5).text("Shadow Softness"));
+        ui.add(egui::Slider::new(&mut params.dapple_count, 0..=100).text("Dapples"));
+        ui.add(egui::Slider::new(&mut params.dapple_size, 0.05..=1.5).text("Dapple Size"));
+        ui.add(egui::Slider::new(&mut params.dapple_alpha, 0.0..=1.0).text("Dapple Alpha"));
+        ui.add(egui::Slider::new(&mut params.dapple_sway, 0.0..=2.0).text("Dapple Sway"));
+
+        ui.heading("Colours");
+        if palettes.bypass_change_detection().ui(ui) {
+            palettes.set_changed();
+        }
+        color_edit(ui, "Ground", &mut params.ground_color);
+        color_edit(ui, "Leaves", &mut params.leaf_color);
+        color_edit(ui, "Mask Leaves", &mut params.mask_leaf_color);
+        color_edit(ui, "Falling Leaves", &mut params.falling_color);
+        color_edit(ui, "Twigs", &mut params.twig_color);
+        color_edit(ui, "Light", &mut params.light_color);
+
+        ui.heading("Random");
+        if rand.bypass_change_detection().ui(ui) {
+            rand.set_changed();
+        }
     });
 }
 
-fn draw(mut painter: ShapePainter, time: Res<Time>, windows: Query<&Window>, params: Res<LeafParams>, rand: Res<CachedRandom>) {
+/// Takes the leaf, ground and light colours from the active palette, or puts back the piece's own without one.
+fn apply_palette(palettes: Res<Palettes>, mut params: ResMut<LeafParams>) {
+    params.ground_color = palettes.pick(Role::Background, DARK_OLIVEGREEN.pastel());
+    params.leaf_color = palettes.pick(Role::Primary, SEA_GREEN.pastel());
+    params.mask_leaf_color = palettes.pick(Role::Secondary, OLIVE_DRAB.pastel());
+    params.light_color = palettes.pick(Role::Accent, LIGHT_YELLOW);
+    let palette = palettes.active();
+    params.falling_color = palette.map_or(FOREST_GREEN.pastel(), |palette| palette.role(Role::Primary).darken(0.1));
+    params.twig_color = palette.map_or(SADDLE_BROWN.pastel(), |palette| palette.role(Role::Secondary).darken(0.2));
+}
+
+/// Rebuilds the gusts from the shared seed, so the same seed blows the same way.
+fn reseed(rand: Res<Random>, mut params: ResMut<LeafParams>) {
+    params.wind_noise = Noise::new(rand.stream("summer_leaves").stream("wind").u64(0));
+}
+
+fn draw(mut painter: Painter, time: Res<Time>, windows: Query<&Window>, params: Res<LeafParams>, falling: Res<FallingLeaves>, rand: Res<Random>) {
     painter.thickness_type = ThicknessType::Screen;
+    painter.describe(&*params);
 
     let seconds = time.elapsed_secs();
+    let rand = rand.stream("summer_leaves");
+    let canopy = params.show_canopy.then(|| params.canopy(&rand, seconds));
+
+    if params.show_light {
+        params.draw_dapples(&mut painter, &rand, seconds);
+        params.draw_shadows(&mut painter, |painter, offset| {
+            if let Some(canopy) = &canopy {
+                params.draw_canopy_leaves(painter, canopy, offset);
+            }
+            params.draw_mask_leaves(painter, &rand, offset);
+            if params.show_debug_grid {
+                params.draw_debug_grid(painter, &rand, offset);
+            }
+            if params.falling {
+                falling.draw(painter, &params, offset);
+            }
+        });
+    }
+
+    if let Some(canopy) = &canopy {
+        params.draw_twigs(&mut painter, canopy);
+        painter.set_color(params.leaf_color);
+        params.draw_canopy_leaves(&mut painter, canopy, Vec3::ZERO);
+    }
+    painter.set_color(params.mask_leaf_color);
+    params.draw_mask_leaves(&mut painter, &rand, Vec3::ZERO);
     if params.show_debug_grid {
-        params.draw_debug_grid(&mut painter, rand);
+        painter.set_color(BLACK);
+        params.draw_debug_grid(&mut painter, &rand, Vec3::ZERO);
+    }
+    if params.falling {
+        painter.set_color(params.falling_color);
+        falling.draw(&mut painter, &params, Vec3::ZERO);
     }
     if params.debug_show_bounds {
         params.draw_bounds(&mut painter);
     }
 }
+
+#[derive(Default)]
+struct Canopy {
+    twigs: Vec<Twig>,
+    /// Position and rotation of each leaf, pointing along the twig it grows from.
+    leaves: Vec<(Vec2, f32)>,
+}
+
+struct Twig {
+    start: Vec2,
+    end: Vec2,
+    depth: usize,
+}
+
+fn simulate_falling(time: Res<Time>, params: Res<LeafParams>, mut falling: ResMut<FallingLeaves>) {
+    if !params.falling {
+        return;
+    }
+    falling.simulate(&params, time.elapsed_secs(), time.delta_secs());
+}
+
+#[derive(Resource, Default)]
+struct FallingLeaves {
+    leaves: Vec<FallingLeaf>,
+    /// Height of the pile in each column along the bottom edge of the bounds.
+    pile: Vec<f32>,
+}
+
+struct FallingLeaf {
+    pos: Vec2,
+    vel: Vec2,
+    rotation: f32,
+    spin: f32,
+    /// Seconds spent lying on the pile, if landed.
+    resting: Option<f32>,
+    column: usize,
+}
+
+impl FallingLeaf {
+    fn spawn(params: &LeafParams, rng: &mut impl Rng) -> Self {
+        let x = rng.random_range(-1.0..=1.0) * params.bounds.x;
+        let y = params.bounds.y + rng.random::<f32>() * params.leaf_size * 4.0;
+        Self {
+            pos: Vec2::new(x, y),
+            vel: Vec2::ZERO,
+            rotation: rng.random::<f32>() * TAU,
+            spin: rng.random_range(-1.0..=1.0) * params.tumble,
+            resting: None,
+            column: 0,
+        }
+    }
+
+    fn step(&mut self, params: &LeafParams, seconds: f32, dt: f32) {
+        let air = Vec2::new(params.wind + params.gust_at(self.pos, seconds) * params.gust, 0.0);
+        let rel = self.vel - air;
+
+        // A tilted leaf deflects the air around it, which pushes it sideways and gives the flutter.
+        let normal = Vec2::new(-self.rotation.sin(), self.rotation.cos());
+        let lift = normal * (self.rotation * 2.0).sin() * rel.length() * params.flutter;
+
+        let accel = Vec2::NEG_Y * params.gravity - rel * params.drag + lift;
+        self.vel += accel * dt;
+        self.spin += (rel.x * params.tumble - self.spin * params.drag) * dt;
+        self.rotation += self.spin * dt;
+        self.pos += self.vel * dt;
+
+        // Wrap around the sides so the wind doesn't blow every leaf out of the bounds.
+        if params.bounds.x > 0.0 {
+            let width = params.bounds.x * 2.0;
+            self.pos.x = (self.pos.x + params.bounds.x).rem_euclid(width) - params.bounds.x;
+        }
+    }
+}
+
+impl FallingLeaves {
+    fn simulate(&mut self, params: &LeafParams, seconds: f32, dt: f32) {
+        let mut rng = rand::rng();
+
+        let columns = ((params.bounds.x * 2.0 / params.leaf_size).ceil() as usize).max(1);
+        if self.pile.len() != columns {
+            self.pile = vec![0.0; columns];
+            for leaf in &mut self.leaves {
+                leaf.resting = None;
+            }
+        }
+
+        let pile_step = params.leaf_size * 0.3;
+        // Leaves dropped from the pile take their share of its height with them.
+        if self.leaves.len() > params.falling_count {
+            for leaf in self.leaves.drain(params.falling_count..) {
+                if leaf.resting.is_some() {
+                    self.pile[leaf.column] = (self.pile[leaf.column] - pile_step).max(0.0);
+                }
+            }
+        }
+        while self.leaves.len() < params.falling_count {
+            let mut leaf = FallingLeaf::spawn(params, &mut rng);
+            // Stagger the first batch through the bounds so they don't fall as a sheet.
+            leaf.pos.y -= rng.random::<f32>() * params.bounds.y * 2.0;
+            self.leaves.push(leaf);
+        }
+
+        let floor = -params.bounds.y;
+        for leaf in &mut self.leaves {
+            match leaf.resting.as_mut() {
+                Some(rested) => {
+                    *rested += dt;
+                    if *rested >= params.rest_time {
+                        self.pile[leaf.column] = (self.pile[leaf.column] - pile_step).max(0.0);
+                        *leaf = FallingLeaf::spawn(params, &mut rng);
+                    }
+                }
+                None => {
+                    leaf.step(params, seconds, dt);
+                    let column = (((leaf.pos.x + params.bounds.x) / params.leaf_size) as usize).min(columns - 1);
+                    if leaf.pos.y <= floor + self.pile[column] {
+                        leaf.pos.y = floor + self.pile[column];
+                        leaf.vel = Vec2::ZERO;
+                        leaf.spin = 0.0;
+                        leaf.column = column;
+                        leaf.resting = Some(0.0);
+                        self.pile[column] += pile_step;
+                    }
+                }
+            }
+        }
+    }
+
+    fn draw(&self, painter: &mut Painter, params: &LeafParams, offset: Vec3) {
+        for (idx, leaf) in self.leaves.iter().enumerate() {
+            params.draw_leaf(painter, leaf.pos.extend(1.1) + offset, leaf.rotation, idx);
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use bevy::app::App;
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContextPass, EguiContexts};
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+use crate::canvas::CanvasCamera;
+use crate::export::Export;
+use crate::{ProgramState, UIState};
+
+/// Previews pieces as they'd look with a color vision deficiency, or printed in grayscale.
+pub struct VisionPlugin;
+
+impl Plugin for VisionPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<VisionPreview>()
+            .add_systems(EguiContextPass, params_ui.run_if(not(in_state(ProgramState::MainMenu))))
+            .add_systems(PreUpdate, track_exports)
+            .add_systems(Update, (shortcuts, preview_clear_color).chain().run_if(not(in_state(ProgramState::MainMenu))))
+        ;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, EnumIter)]
+pub enum Vision {
+    #[default]
+    Normal,
+    Protanopia,
+    Deuteranopia,
+    Tritanopia,
+    Grayscale,
+}
+
+impl Vision {
+    /// How the vision maps linear RGB, one row per output channel.
+    /// The deficiencies are Machado, Oliveira and Fernandes' full severity matrices.
+    fn matrix(&self) -> Option<[[f32; 3]; 3]> {
+        match self {
+            Vision::Normal => None,
+            Vision::Protanopia => Some([
+                [0.152286, 1.052583, -0.204868],
+                [0.114503, 0.786281, 0.099216],
+                [-0.003882, -0.048116, 1.051998],
+            ]),
+            Vision::Deuteranopia => Some([
+                [0.367322, 0.860646, -0.227968],
+                [0.280085, 0.672501, 0.047413],
+                [-0.011820, 0.042940, 0.968881],
+            ]),
+            Vision::Tritanopia => Some([
+                [1.255528, -0.076749, -0.178779],
+                [-0.078411, 0.930809, 0.147602],
+                [0.004733, 0.691367, 0.303900],
+            ]),
+            Vision::Grayscale => Some([[0.2126, 0.7152, 0.0722]; 3]),
+        }
+    }
+
+    /// Since the simulation is linear, simulating each color before blending matches simulating the blended frame.
+    pub fn simulate(&self, color: Color) -> Color {
+        let Some(rows) = self.matrix() else {
+            return color;
+        };
+        let linear = LinearRgba::from(color);
+        let rgb = [linear.red, linear.green, linear.blue];
+        let [red, green, blue] = rows.map(|row| (row[0] * rgb[0] + row[1] * rgb[1] + row[2] * rgb[2]).clamp(0.0, 1.0));
+        LinearRgba::new(red, green, blue, linear.alpha).into()
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct VisionPreview {
+    pub vision: Vision,
+    /// Simulate in exported files too, rather than only on screen.
+    pub exports: bool,
+    /// Whether an image export is rendering, which sees the same shapes as the screen.
+    exporting: bool,
+}
+
+impl VisionPreview {
+    /// `color` as it should be drawn to the screen.
+    pub fn for_screen(&self, color: Color) -> Color {
+        if self.exporting && !self.exports {
+            color
+        } else {
+            self.vision.simulate(color)
+        }
+    }
+
+    /// `color` as it should be written to an exported file.
+    pub fn for_export(&self, color: Color) -> Color {
+        if self.exports {
+            self.vision.simulate(color)
+        } else {
+            color
+        }
+    }
+}
+
+/// Image exports render the same shapes as the screen, so they're drawn unfiltered while one runs unless asked otherwise.
+fn track_exports(export: Res<Export>, mut preview: ResMut<VisionPreview>) {
+    let exporting = export.is_rendering();
+    if preview.exporting != exporting {
+        preview.exporting = exporting;
+    }
+}
+
+/// Filters the background along with the shapes.
+fn preview_clear_color(preview: Res<VisionPreview>, clear_color: Res<ClearColor>, mut cameras: Query<&mut Camera, With<CanvasCamera>>) {
+    if !preview.is_changed() && !clear_color.is_changed() {
+        return;
+    }
+    for mut camera in &mut cameras {
+        camera.clear_color = ClearColorConfig::Custom(preview.for_screen(clear_color.0));
+    }
+}
+
+/// `V` steps through the simulations, unless a text field is being typed in.
+fn shortcuts(mut contexts: EguiContexts, keys: Res<ButtonInput<KeyCode>>, mut preview: ResMut<VisionPreview>) {
+    if contexts.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+    if keys.just_pressed(KeyCode::KeyV) {
+        let mut visions = Vision::iter().cycle().skip_while(|vision| *vision != preview.vision);
+        visions.next();
+        preview.vision = visions.next().unwrap_or_default();
+    }
+}
+
+fn params_ui(
+    mut contexts: EguiContexts,
+    ui_state: Res<UIState>,
+    mut preview: ResMut<VisionPreview>,
+) {
+    if !ui_state.params_panel {
+        return;
+    }
+
+    egui::Window::new("Vision").show(contexts.ctx_mut(), |ui| {
+        egui::ComboBox::from_label("Simulate (V)")
+            .selected_text(format!("{:?}", preview.vision))
+            .show_ui(ui, |ui| {
+                for vision in Vision::iter() {
+                    ui.selectable_value(&mut preview.vision, vision, format!("{:?}", vision));
+                }
+            });
+        ui.checkbox(&mut preview.exports, "Apply to exports");
+    });
+}